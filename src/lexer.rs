@@ -1,9 +1,12 @@
 use logos::Logos;
+use std::ops::Range;
 
 
 #[derive(Debug, Copy, Clone, PartialEq, Logos)]
-#[logos(skip "\r")]
-#[logos(skip "[ \t]*//[^\n]*")]
+#[logos(skip "[ \t]*//[^\r\n]*")]
+// block comments take the whitespace after them so indentation before them is kept. They can't
+// span lines, since skipping a newline would join two statements.
+#[logos(skip r"/\*[^*\r\n]*\*+([^/*\r\n][^*\r\n]*\*+)*/[ \t]*")]
 pub enum Token<'a> {
     #[token("let", |_|Keyword::Let)]
     #[token("mut", |_|Keyword::Mut)]
@@ -19,14 +22,35 @@ pub enum Token<'a> {
     #[token("and", |_|Keyword::And)]
     #[token("or", |_|Keyword::Or)]
     #[token("return", |_|Keyword::Return)]
+    #[token("break", |_|Keyword::Break)]
+    #[token("continue", |_|Keyword::Continue)]
+    #[token("as", |_|Keyword::As)]
+    #[token("infix", |_|Keyword::Infix)]
+    #[token("heap", |_|Keyword::Heap)]
+    #[token("stack", |_|Keyword::Stack)]
+    #[token("where", |_|Keyword::Where)]
+    #[token("typeof", |_|Keyword::TypeOf)]
+    #[token("const", |_|Keyword::Const)]
+    #[token("loop", |_|Keyword::Loop)]
+    #[token("assert_type", |_|Keyword::AssertType)]
     Keyword(Keyword),
 
     #[regex("[A-Za-z_][A-Za-z0-9_]*")]
     Word(&'a str),
-    #[regex("[0-9][0-9_]*")]
+    /// Underscores are only allowed between digits, so `1_` is `Number("1")` followed by
+    /// `Word("_")`.
+    /// `0x`, `0b`, and `0o` start hex, binary, and octal numbers.
+    #[regex("[0-9](_?[0-9])*")]
+    #[regex("0x[0-9A-Fa-f](_?[0-9A-Fa-f])*")]
+    #[regex("0b[01](_?[01])*")]
+    #[regex("0o[0-7](_?[0-7])*")]
     Number(&'a str),
     #[regex("\"[^\"]*\"")]
     String(&'a str),
+    #[regex(r"'([^'\\\n]|\\[^\n{]|\\x[0-9A-Fa-f]*|\\u\{[0-9A-Fa-f]*\})'")]
+    Char(&'a str),
+    #[regex("'[A-Za-z_][A-Za-z0-9_]*", |s|s.slice().trim_start_matches('\''))]
+    Label(&'a str),
 
     // Enclosing punctuation
     #[token("{")]
@@ -48,12 +72,19 @@ pub enum Token<'a> {
     Comma,
     #[token("~")]
     Tilde,
+    #[token(":")]
+    Colon,
     #[token("=")]
     Assign,
     #[token(";")]
     Semicolon,
     #[token("=>")]
     FatArrow,
+    #[token("->")]
+    Arrow,
+    /// Starts a `#[cfg(feature)]` marker.
+    #[token("#")]
+    Hash,
 
     // Arithmetic
     #[token("+")]
@@ -64,6 +95,8 @@ pub enum Token<'a> {
     Mul,
     #[token("/")]
     Div,
+    #[token("%")]
+    Rem,
     #[token("&")]
     And,
     #[token("|")]
@@ -90,19 +123,87 @@ pub enum Token<'a> {
     // Misc.
     #[token(".")]
     FieldIndex,
+    /// An operator like `<+>` that a program declares with `infix`. The lexer can't back out of
+    /// a symbol that never closes, so it can't start with anything that is allowed after `<`, like
+    /// the `-` in `a<-1` or the `=` in `a<=b`.
+    #[regex(r"<[+/%|^!~.$@:?][-+*/%&|^!~=.$@:?]*>")]
+    Symbol(&'a str),
 
     // Whitespace
+    /// Includes any trailing whitespace so it is never mistaken for function application. `\r\n`
+    /// and a lone `\r` end lines just like `\n`, so indentation is counted the same for all three.
     #[regex("[ \t]*[\n\r]+")]
     Newline,
     #[regex("[ \t]+", |s|s.slice().len())]
+    /// A `\` at the end of a line continues the statement on the next line. The line break and
+    /// the next line's indentation act as a single space.
+    #[regex(r"[ \t]*\\[ \t]*(\r\n?|\n)[ \t]*", |_|1)]
     Whitespace(usize),
 
     EOF,
 }
+impl<'a> Token<'a> {
+    /// Checks if the token is a keyword, including the word operators `and`, `or`, and `as`.
+    pub fn is_keyword(&self)->bool {
+        matches!(self, Token::Keyword(_))
+    }
+
+    /// Checks if the token is an arithmetic, bitwise, or comparison operator, a field access, or an
+    /// operator declared with `infix`.
+    pub fn is_operator(&self)->bool {
+        match self {
+            Token::Add|
+                Token::Sub|
+                Token::Mul|
+                Token::Div|
+                Token::Rem|
+                Token::And|
+                Token::Or|
+                Token::Xor|
+                Token::Not|
+                Token::Equal|
+                Token::NotEqual|
+                Token::Less|
+                Token::LessEqual|
+                Token::Greater|
+                Token::GreaterEqual|
+                Token::FieldIndex|
+                Token::Symbol(_)=>true,
+            _=>false,
+        }
+    }
+
+    /// Checks if the token is a number, string, or char literal.
+    pub fn is_literal(&self)->bool {
+        matches!(self, Token::Number(_)|Token::String(_)|Token::Char(_))
+    }
+
+    /// Checks if the token is indentation, spacing, or a line break.
+    pub fn is_whitespace(&self)->bool {
+        matches!(self, Token::Whitespace(_)|Token::Newline)
+    }
+}
 impl<'a> parser_helper::Token for Token<'a> {
     fn eof()->Self {Self::EOF}
 }
 
+/// Lexes the whole source into tokens and their byte spans. `Whitespace` and `Newline` tokens are
+/// only kept if `keep_whitespace` is set. Characters that don't form a token are left out.
+pub fn tokenize(source: &str, keep_whitespace: bool)->Vec<(Token<'_>, Range<usize>)> {
+    let mut lexer = Token::lexer(source);
+    let mut tokens = Vec::new();
+
+    while let Some(token) = lexer.next() {
+        match token {
+            Ok(Token::Whitespace(_)|Token::Newline) if !keep_whitespace=>{},
+            Ok(token)=>tokens.push((token, lexer.span())),
+            Err(_)=>{},
+        }
+    }
+
+    return tokens;
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Logos)]
 pub enum Keyword {
     Let,
@@ -119,4 +220,160 @@ pub enum Keyword {
     And,
     Or,
     Return,
+    Break,
+    Continue,
+    As,
+    Infix,
+    Heap,
+    Stack,
+    Where,
+    TypeOf,
+    Const,
+    Loop,
+    AssertType,
+}
+impl Keyword {
+    /// Every keyword. The `#[token]` attributes on `Token::Keyword` must list the same keywords.
+    pub const ALL: &'static [Keyword] = &[
+        Keyword::Let,
+        Keyword::Mut,
+        Keyword::Set,
+        Keyword::Func,
+        Keyword::Proc,
+        Keyword::Type,
+        Keyword::Disown,
+        Keyword::Scope,
+        Keyword::If,
+        Keyword::Else,
+        Keyword::Cond,
+        Keyword::And,
+        Keyword::Or,
+        Keyword::Return,
+        Keyword::Break,
+        Keyword::Continue,
+        Keyword::As,
+        Keyword::Infix,
+        Keyword::Heap,
+        Keyword::Stack,
+        Keyword::Where,
+        Keyword::TypeOf,
+        Keyword::Const,
+        Keyword::Loop,
+        Keyword::AssertType,
+    ];
+
+    /// The source text of the keyword.
+    pub fn as_str(&self)->&'static str {
+        match self {
+            Keyword::Let=>"let",
+            Keyword::Mut=>"mut",
+            Keyword::Set=>"set",
+            Keyword::Func=>"func",
+            Keyword::Proc=>"proc",
+            Keyword::Type=>"type",
+            Keyword::Disown=>"disown",
+            Keyword::Scope=>"scope",
+            Keyword::If=>"if",
+            Keyword::Else=>"else",
+            Keyword::Cond=>"cond",
+            Keyword::And=>"and",
+            Keyword::Or=>"or",
+            Keyword::Return=>"return",
+            Keyword::Break=>"break",
+            Keyword::Continue=>"continue",
+            Keyword::As=>"as",
+            Keyword::Infix=>"infix",
+            Keyword::Heap=>"heap",
+            Keyword::Stack=>"stack",
+            Keyword::Where=>"where",
+            Keyword::TypeOf=>"typeof",
+            Keyword::Const=>"const",
+            Keyword::Loop=>"loop",
+            Keyword::AssertType=>"assert_type",
+        }
+    }
+
+    /// Returns the keyword spelled by the word, if any.
+    pub fn from_word(word: &str)->Option<Keyword> {
+        Self::ALL.iter()
+            .copied()
+            .find(|keyword|keyword.as_str() == word)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underscores_only_between_digits() {
+        let tokens = |source|{
+            tokenize(source, false)
+                .into_iter()
+                .map(|(token, _)|token)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(tokens("1_0"), [Token::Number("1_0")]);
+        // a trailing underscore isn't part of the number, so it is a word after it
+        assert_eq!(tokens("1_"), [Token::Number("1"), Token::Word("_")]);
+    }
+
+    #[test]
+    fn tokenize_gives_spans() {
+        assert_eq!(tokenize("let x = 1\n", false), [
+            (Token::Keyword(Keyword::Let), 0..3),
+            (Token::Word("x"), 4..5),
+            (Token::Assign, 6..7),
+            (Token::Number("1"), 8..9),
+        ]);
+
+        let with_whitespace = tokenize("f\n    x", true)
+            .into_iter()
+            .map(|(token, _)|token)
+            .collect::<Vec<_>>();
+        assert_eq!(with_whitespace, [
+            Token::Word("f"),
+            Token::Newline,
+            Token::Whitespace(4),
+            Token::Word("x"),
+        ]);
+    }
+
+    #[test]
+    fn keywords_lex_like_their_spelling() {
+        for keyword in Keyword::ALL.iter().copied() {
+            let word = keyword.as_str();
+
+            assert_eq!(Keyword::from_word(word), Some(keyword));
+            let mut lexer = Token::lexer(word);
+            assert_eq!(lexer.next(), Some(Ok(Token::Keyword(keyword))), "`{word}` should lex");
+            assert_eq!(lexer.next(), None);
+        }
+
+        assert_eq!(Keyword::from_word("lets"), None);
+        assert_eq!(Token::lexer("lets").next(), Some(Ok(Token::Word("lets"))));
+    }
+
+    #[test]
+    fn tokens_are_classified() {
+        let classes = |token: Token|{
+            (token.is_keyword(), token.is_operator(), token.is_literal(), token.is_whitespace())
+        };
+
+        for (token, _) in tokenize("let and as", false) {
+            assert_eq!(classes(token), (true, false, false, false), "{token:?}");
+        }
+        for (token, _) in tokenize("+ - * == <= . <+>", false) {
+            assert_eq!(classes(token), (false, true, false, false), "{token:?}");
+        }
+        for (token, _) in tokenize("1 0xFF \"s\" 'c'", false) {
+            assert_eq!(classes(token), (false, false, true, false), "{token:?}");
+        }
+        for (token, _) in tokenize("x\n    y", true) {
+            let whitespace = matches!(token, Token::Newline|Token::Whitespace(_));
+            assert_eq!(classes(token), (false, false, false, whitespace), "{token:?}");
+        }
+    }
 }
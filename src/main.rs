@@ -42,8 +42,12 @@
 
 
 use indexmap::IndexSet;
-use logos::Logos;
 use std::fs::read_to_string;
+use mid_ast::tree::{
+    Diagnostic,
+    ErrorFormat,
+    ProgramError,
+};
 
 
 mod lexer;
@@ -68,6 +72,27 @@ impl<'a> StringInterner<'a> {
         }
     }
 
+    /// Create a new StringInterner with room for `capacity` strings.
+    pub fn with_capacity(capacity: usize)->Self {
+        StringInterner {
+            strings: IndexSet::with_capacity(capacity),
+        }
+    }
+
+    /// Reserve room for at least `additional` more strings.
+    pub fn reserve(&mut self, additional: usize) {
+        self.strings.reserve(additional);
+    }
+
+    /// Counts the interned strings and their total length.
+    pub fn stats(&self)->InternerStats {
+        InternerStats {
+            strings: self.strings.len(),
+            bytes: self.strings.iter().map(|s|s.len()).sum(),
+            capacity: self.strings.capacity(),
+        }
+    }
+
     /// Intern the string and return the index.
     pub fn intern(&mut self, s: &'a str)->Index {
         Index(self.strings.insert_full(s).0)
@@ -85,29 +110,141 @@ impl<'a> StringInterner<'a> {
 }
 
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InternerStats {
+    /// The number of unique strings.
+    pub strings: usize,
+    /// The total length of the unique strings.
+    pub bytes: usize,
+    /// The number of strings that fit without reallocating.
+    pub capacity: usize,
+}
+impl InternerStats {
+    /// The average length of the unique strings, or 0 if there are none.
+    pub fn average_len(&self)->f64 {
+        if self.strings == 0 {
+            return 0.0;
+        }
+
+        return self.bytes as f64 / self.strings as f64;
+    }
+}
+
+
 fn main() {
-    let file = read_to_string("example").unwrap();
-    for token in lexer::Token::lexer(&file) {
-        dbg!(token).ok();
+    let mut error_format = ErrorFormat::Human;
+    let mut check_only = false;
+    let mut preserve_parens = false;
+    let mut path = None;
+    let mut features = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--error-format=human"=>error_format = ErrorFormat::Human,
+            "--error-format=json"=>error_format = ErrorFormat::Json,
+            "--check"=>check_only = true,
+            "--preserve-parens"=>preserve_parens = true,
+            _ if arg.starts_with("--cfg=")=>features.push(arg["--cfg=".len()..].to_string()),
+            _ if arg.starts_with("--")=>{
+                eprintln!("Unknown argument `{arg}`");
+                return;
+            },
+            _=>path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(||"example".to_string());
+
+    let source = read_to_string(&path).unwrap();
+    let features = features.iter().map(String::as_str).collect::<Vec<_>>();
+
+    if check_only {
+        check(&source, &path, &features, error_format);
+        return;
     }
 
-    let mut parser = parser::Parser::new(&file);
-    let res = dbg!(parser.parse_file());
-    match res {
-        Ok(items)=>{
+    for token in lexer::tokenize(&source, true) {
+        dbg!(token);
+    }
+
+    let mut parser = parser::Parser::new(&source);
+    if preserve_parens {
+        parser = parser.preserve_parens();
+    }
+
+    match parser.parse_all() {
+        Ok((items, interner, warnings))=>{
+            dbg!(&items);
             for item in &items {
-                item.print(&parser.interner, 0);
+                item.print(&interner, 0);
                 println!();
             }
 
-            for (i, s) in parser.interner.strings.iter().enumerate() {
+            for (i, s) in interner.strings.iter().enumerate() {
                 println!("{i}: \"{s}\"");
             }
 
-            dbg!(mid_ast::conversion::convert_parse_tree(items));
+            let file = mid_ast::tree::File::from_parse_tree(
+                items,
+                warnings,
+                &source,
+                &interner,
+                &features,
+            );
+            eprint_diagnostics(&file.diagnostics, error_format, &source, &path);
+            dbg!(file);
         },
         Err(e)=>{
-            e.eprint_with_source(&file, "example");
+            dbg!(&e);
+            ProgramError::Parse(e).eprint(error_format, &source, &path);
+        },
+    }
+}
+
+/// Prints the diagnostics to stderr in the given format.
+fn eprint_diagnostics(
+    diagnostics: &[Diagnostic],
+    error_format: ErrorFormat,
+    source: &str,
+    filename: &str,
+) {
+    match error_format {
+        ErrorFormat::Human=>for diagnostic in diagnostics {
+            diagnostic.eprint_with_source(source, filename);
         },
+        ErrorFormat::Json=>eprintln!(
+            "{}",
+            mid_ast::tree::diagnostics_to_json(diagnostics, filename),
+        ),
+    }
+}
+
+/// Runs every pass on the source, but only prints the diagnostics. Exits with an error code if
+/// there are any errors.
+fn check(source: &str, filename: &str, features: &[&str], error_format: ErrorFormat) {
+    match mid_ast::tree::File::parse_with_features(source, features) {
+        Ok((file, _))=>eprint_diagnostics(&file.diagnostics, error_format, source, filename),
+        Err(e)=>{
+            e.eprint(error_format, source, filename);
+            std::process::exit(1);
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_stats_count_unique_strings() {
+        let mut interner = StringInterner::with_capacity(8);
+        interner.intern("main");
+        interner.intern("x");
+        interner.intern("main");
+
+        let stats = interner.stats();
+        assert_eq!((stats.strings, stats.bytes), (2, 5));
+        assert!(stats.capacity >= 8);
+        assert_eq!(stats.average_len(), 2.5);
+        assert_eq!(StringInterner::new().stats().average_len(), 0.0);
     }
 }
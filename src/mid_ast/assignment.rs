@@ -0,0 +1,242 @@
+// definite assignment of variables declared without a value
+
+
+use fnv::FnvHashSet;
+use crate::StringInterner;
+use super::tree::*;
+
+
+/// Checks that every variable declared with a bare `let x` is assigned on every path before it is
+/// used. Only the first use before assignment is reported for each variable.
+///
+/// A `break` carries whether the variable was assigned to the statement after its `scope`, and a
+/// `continue` never does, since the variable was not any less assigned when the scope started.
+pub fn check_assignments(file: &mut File, interner: &StringInterner) {
+    let mut diagnostics = Vec::new();
+
+    // blocks that don't run in order with the statements around them
+    let branches = file.all_stmts()
+        .flat_map(|(_, stmt)|match stmt {
+            Stmt::IfElse{block, else_block, ..}=>{
+                let else_scope = else_block.as_ref().map(|block|block.scope);
+                vec![Some(block.scope), else_scope]
+            },
+            Stmt::Conditional{actions, default, ..}=>actions.iter()
+                .chain(default)
+                .map(|action|match action {
+                    ConditionalAction::Scope(block)=>Some(block.scope),
+                    ConditionalAction::Expr(_)=>None,
+                })
+                .collect(),
+            _=>Vec::new(),
+        })
+        .flatten()
+        .chain(file.functions.iter().map(|function|function.block.scope))
+        .collect::<FnvHashSet<_>>();
+
+    for (index, var) in file.vars.iter().enumerate() {
+        let index = VarIndex(index);
+        let is_bare = match file.get_stmt(var.definition) {
+            Stmt::VarDef(def)=>*def == index && var.init.is_none(),
+            _=>false,
+        };
+        if !is_bare {
+            continue;
+        }
+
+        let mut walker = Walker {
+            file,
+            branches: &branches,
+            var: index,
+            sites: var.uses.iter()
+                .chain(&var.borrows)
+                .chain(&var.mut_borrows)
+                .chain(&var.derefs)
+                .chain(&var.disown)
+                .copied()
+                .collect(),
+            first_use: None,
+            breaks: Vec::new(),
+        };
+
+        let scope = file.get_scope(var.in_scope);
+        let start = StmtIndex {
+            root: var.definition.root + 1,
+            patch: 0,
+        };
+        walker.walk(var.in_scope, start, scope.last, Some(false));
+
+        if let Some(site) = walker.first_use {
+            let mut msg = format!(
+                "`{}` is used before it is assigned on every path",
+                interner.get_string(var.name),
+            );
+            if let Some(context) = file.scope_context(var.in_scope, interner) {
+                msg = format!("{msg} ({context})");
+            }
+            diagnostics.push(match file.site_span(index, site) {
+                Some(span)=>Diagnostic::error(msg).with_span(span),
+                None=>Diagnostic::error(msg),
+            });
+        }
+    }
+
+    file.diagnostics.append(&mut diagnostics);
+}
+
+/// Whether the variable is assigned at a point in the program, or `None` if the point is never
+/// reached.
+type State = Option<bool>;
+
+fn merge(a: State, b: State)->State {
+    match (a, b) {
+        (Some(a), Some(b))=>Some(a && b),
+        (a, None)=>a,
+        (None, b)=>b,
+    }
+}
+
+struct Walker<'a> {
+    file: &'a File,
+    /// The scopes of `if` blocks and function bodies, which are only walked from the statement
+    /// that runs them.
+    branches: &'a FnvHashSet<ScopeIndex>,
+    var: VarIndex,
+    /// Every statement that reads the variable.
+    sites: FnvHashSet<StmtIndex>,
+    first_use: Option<StmtIndex>,
+    /// The target of every `break` taken so far, and the state it carries there.
+    breaks: Vec<(StmtIndex, State)>,
+}
+impl<'a> Walker<'a> {
+    /// Walks the statements of the scope from `first` to `last` in order. Nested `scope` blocks
+    /// are walked where they are, while `if` blocks are walked by their `IfElse` statement and
+    /// function bodies are skipped.
+    fn walk(
+        &mut self,
+        scope: ScopeIndex,
+        first: StmtIndex,
+        last: StmtIndex,
+        mut state: State,
+    )->State {
+        let file = self.file;
+        let children = file.get_scope(scope).scopes
+            .iter()
+            .map(|child|(*child, file.get_scope(*child)))
+            .collect::<Vec<_>>();
+        let mut walked = None;
+
+        for root in first.root..last.root.wrapping_add(1) {
+            state = self.take_breaks(root, state);
+
+            for (index, stmt) in file.stmt_with_patches(root) {
+                if !index.within(first, last) {
+                    continue;
+                }
+
+                let child = children.iter()
+                    .find(|(_, child)|index.within(child.first, child.last));
+                match child {
+                    // a child is walked all at once when its first statement is reached
+                    Some((child_index, child))=>if walked != Some(*child_index) {
+                        walked = Some(*child_index);
+                        if !self.branches.contains(child_index) {
+                            state = self.walk(*child_index, child.first, child.last, state);
+                        }
+                    },
+                    None=>state = self.step(index, stmt, state),
+                }
+            }
+        }
+
+        return self.take_breaks(last.root.wrapping_add(1), state);
+    }
+
+    fn walk_block(&mut self, block: &Block, state: State)->State {
+        self.walk(block.scope, block.first, block.last, state)
+    }
+
+    /// Merges the state of every `break` that lands on the root statement.
+    fn take_breaks(&mut self, root: usize, mut state: State)->State {
+        self.breaks.retain(|(target, carried)|{
+            if target.root == root {
+                state = merge(state, *carried);
+                false
+            } else {
+                true
+            }
+        });
+
+        return state;
+    }
+
+    fn step(&mut self, index: StmtIndex, stmt: &Stmt, state: State)->State {
+        let assigned = match state {
+            Some(assigned)=>assigned,
+            None=>return None,
+        };
+
+        if !assigned && self.first_use.is_none() && self.sites.contains(&index) {
+            self.first_use = Some(index);
+        }
+
+        match stmt {
+            Stmt::VarSet{var, ..} if *var == self.var=>Some(true),
+            Stmt::VarSetMulti{vars, ..} if vars.contains(&self.var)=>Some(true),
+            Stmt::IfElse{block, else_block, ..}=>{
+                let taken = self.walk_block(block, state);
+                let not_taken = match else_block {
+                    Some(block)=>self.walk_block(block, state),
+                    None=>state,
+                };
+                merge(taken, not_taken)
+            },
+            Stmt::Conditional{actions, default, ..}=>{
+                let mut after = match default {
+                    Some(ConditionalAction::Scope(block))=>self.walk_block(block, state),
+                    _=>state,
+                };
+                for action in actions {
+                    if let ConditionalAction::Scope(block) = action {
+                        let taken = self.walk_block(block, state);
+                        after = merge(after, taken);
+                    }
+                }
+                after
+            },
+            Stmt::Return{..}=>None,
+            Stmt::JumpTo(target)=>{
+                if *target > index {
+                    self.breaks.push((*target, state));
+                }
+                None
+            },
+            _=>state,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_before_assignment_are_errors() {
+        let assigned = concat!(
+            "func f c\n    let mut x\n",
+            "    if c\n        set x = 1\n    else\n        set x = 2\n",
+            "    x\n",
+        );
+        assert!(File::parse(assigned).is_ok());
+
+        let source = "func f c\n    let mut x\n    if c\n        set x = 1\n    x\n";
+        let Err(ProgramError::Diagnostics(diagnostics)) = File::parse(source) else {
+            panic!("using `x` when the `if` isn't taken should be an error");
+        };
+        let error = diagnostics.iter()
+            .find(|d|d.msg.starts_with("`x` is used before it is assigned"))
+            .unwrap();
+        assert_eq!(error.span, Some(source.len() - 2..source.len() - 1));
+    }
+}
@@ -0,0 +1,125 @@
+// cleanup after optimization
+
+
+use std::mem;
+use super::tree::*;
+
+
+enum Collapse {
+    /// Replace the expression with this one.
+    Set(Expr),
+    /// Move the given operand into the expression's place.
+    Hoist(ExprIndex),
+}
+
+
+/// Removes the `Skip` placeholders left by optimizations so later passes never see them. Skipped
+/// items are dropped from groups, an operation with one skipped operand becomes the other operand,
+/// and anything else wrapping a `Skip` becomes a `Skip` itself. A statement that only evaluated a
+/// skipped expression becomes a skipped statement.
+///
+/// Operands only become `Skip` when they can never run, so dropping them doesn't change anything.
+pub fn collapse_skips(file: &mut File) {
+    // hoisting moves a whole expression, so repeat until nothing changes
+    loop {
+        let collapses = file.all_exprs()
+            .filter_map(|(index, _)|Some((index, collapse(file, index)?)))
+            .collect::<Vec<_>>();
+
+        if collapses.len() == 0 {
+            break;
+        }
+
+        for (index, collapse) in collapses {
+            let expr = match collapse {
+                Collapse::Set(expr)=>expr,
+                Collapse::Hoist(operand)=>mem::replace(file.get_mut_expr(operand), Expr::Skip),
+            };
+            *file.get_mut_expr(index) = expr;
+        }
+    }
+
+    let skipped = file.all_stmts()
+        .filter(|(_, stmt)|match stmt {
+            Stmt::Expr(expr)=>is_skip(file, *expr),
+            _=>false,
+        })
+        .map(|(index, _)|index)
+        .collect::<Vec<_>>();
+    for index in skipped {
+        *file.get_mut_stmt(index) = Stmt::Skip;
+    }
+}
+
+fn collapse(file: &File, index: ExprIndex)->Option<Collapse> {
+    match file.get_expr(index) {
+        Expr::Group(items) if items.iter().any(|item|is_skip(file, *item))=>{
+            let items = items.iter()
+                .copied()
+                .filter(|item|!is_skip(file, *item))
+                .collect();
+            Some(Collapse::Set(Expr::Group(items)))
+        },
+        Expr::Operation{left, right, ..}=>match (is_skip(file, *left), is_skip(file, *right)) {
+            (true, true)=>Some(Collapse::Set(Expr::Skip)),
+            (true, false)=>Some(Collapse::Hoist(*right)),
+            (false, true)=>Some(Collapse::Hoist(*left)),
+            (false, false)=>None,
+        },
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Borrow(inner)|
+            Expr::BorrowMut(inner)|
+            Expr::Deref(inner)|
+            Expr::Negate(inner)|
+            Expr::Heap(inner)|
+            Expr::Stack(inner) if is_skip(file, *inner)=>Some(Collapse::Set(Expr::Skip)),
+        _=>None,
+    }
+}
+
+#[inline]
+fn is_skip(file: &File, expr: ExprIndex)->bool {
+    matches!(file.get_expr(expr), Expr::Skip)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::{
+        *,
+        super::conversion::convert_parse_tree,
+    };
+
+    #[test]
+    fn skipped_operands_are_removed() {
+        let source = "func f (x, y)\n    let z = (x, y + x)\n    z\n";
+        let (stmts, interner, _) = Parser::new(source).parse_all().unwrap();
+        let mut file = convert_parse_tree(stmts, source, &interner, &[]);
+
+        let (group, items) = file.all_exprs()
+            .find_map(|(index, expr)|match expr {
+                Expr::Group(items)=>Some((index, items.clone())),
+                _=>None,
+            })
+            .unwrap();
+        let Expr::Operation{left: y, right, ..} = *file.get_expr(items[1]) else {
+            panic!("the second item should be `y + x`");
+        };
+
+        // as if both `x`s were optimized out
+        *file.get_mut_expr(items[0]) = Expr::Skip;
+        *file.get_mut_expr(right) = Expr::Skip;
+        let y = file.get_expr(y).clone();
+        collapse_skips(&mut file);
+
+        // the skipped `x` is dropped from the group, and `y + <skip>` becomes `y`
+        let Expr::Group(items) = file.get_expr(group) else {
+            panic!("the group should stay a group");
+        };
+        assert_eq!(items.len(), 1);
+        assert!(matches!((file.get_expr(items[0]), y), (Expr::Var(a), Expr::Var(b)) if *a == b));
+    }
+}
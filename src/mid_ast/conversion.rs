@@ -1,43 +1,586 @@
 use std::{
     collections::HashMap,
+    ops::Range,
     rc::Rc,
 };
-use fnv::FnvHashMap;
 use crate::{
     parser::{
         Stmt as PStmt,
         Expr as PExpr,
         ConditionalAction as PCondAct,
         Block as PBlock,
+        ReturnType as PReturnType,
     },
     Index,
     Name,
+    StringInterner,
 };
-use super::tree::*;
+use super::{
+    tree::*,
+    effects::callee,
+    folding::fold,
+};
+
 
+/// Names that are provided by the runtime and are never defined in a file.
+const BUILTINS: &[&str] = &["print"];
 
-struct FileConversion {
+
+struct FileConversion<'a> {
     file: File,
+    interner: &'a StringInterner<'a>,
 
     raw_func_queue: Vec<RawFunction>,
+    /// The stack of `scope` blocks that can be broken out of, innermost last.
+    breakables: Vec<Breakable>,
+    /// The functions whose bodies are being converted, innermost last. `return`s belong to the
+    /// last one.
+    function_stack: Vec<FunctionIndex>,
+    /// The scope that statements and expressions are currently being converted in.
+    current_scope: ScopeIndex,
+    /// Every name use and the scope it is in. Variables are resolved as they are converted, but a
+    /// function in a closer scope can still shadow them, so all of these are checked again once
+    /// every function has been converted.
+    unresolved: Vec<(ExprIndex, ScopeIndex, Range<usize>)>,
+    /// The features that `#[cfg(feature)]` statements are kept for.
+    features: &'a [&'a str],
+    /// The spans of the `loop`s in the function being converted that are never left.
+    endless_loops: Vec<Range<usize>>,
+    /// The expression and type of every `assert_type`. They aren't statements, since the
+    /// expression is never evaluated.
+    type_assertions: Vec<(ExprIndex, Type)>,
 }
-impl FileConversion {
-    fn convert(stmts: Vec<PStmt>)->File {
+impl<'a> FileConversion<'a> {
+    fn convert(
+        stmts: Vec<PStmt>,
+        source: &str,
+        interner: &'a StringInterner<'a>,
+        features: &'a [&'a str],
+    )->File {
         let mut this = FileConversion {
-            file: File::new(),
+            file: File::with_source(source),
+            interner,
+            features,
             raw_func_queue: Vec::new(),
+            breakables: Vec::new(),
+            function_stack: Vec::new(),
+            current_scope: ScopeIndex::invalid(),
+            unresolved: Vec::new(),
+            endless_loops: Vec::new(),
+            type_assertions: Vec::new(),
         };
 
-        this.file.root_scope = this.convert_block(PBlock(stmts)).scope;
+        this.file.root_scope = this.convert_block(None, PBlock(stmts)).scope;
 
-        // convert all of the functions in some random order
-        while let Some(raw_function) = this.raw_func_queue.pop() {
-            this.convert_function(raw_function);
+        // convert all of the functions in source order, one nesting level at a time
+        while this.raw_func_queue.len() > 0 {
+            for raw_function in std::mem::take(&mut this.raw_func_queue) {
+                this.convert_function(raw_function);
+            }
         }
 
+        this.resolve_names();
+        this.check_callable();
+        this.check_arity();
+        this.check_return_types();
+        this.check_conditions();
+        this.resolve_typeof();
+        this.check_type_assertions();
+        this.resolve_output_fields();
+        this.find_entry_point();
+
         return this.file;
     }
 
+    /// Checks if the name is `_`, which is never bound.
+    fn is_discard(&self, name: Name)->bool {
+        self.interner.get_string(name) == "_"
+    }
+
+    /// Finds the most recent definition of the variable visible from the current scope.
+    fn lookup_var(&self, name: Name)->Option<VarIndex> {
+        let mut scope = Some(self.current_scope);
+
+        while let Some(index) = scope {
+            let scope_ref = self.file.get_scope(index);
+            if let Some(var) = scope_ref.vars.get(&name).and_then(|vars|vars.last()) {
+                return Some(*var);
+            }
+
+            scope = scope_ref.parent;
+        }
+
+        return None;
+    }
+
+    /// Resolves the variable being assigned to, reporting an error if it doesn't exist or is a
+    /// `const`.
+    fn assign_target(&mut self, name: Name, span: Range<usize>)->VarIndex {
+        match self.lookup_var(name) {
+            Some(var)=>{
+                // only `const`s have their values folded this early
+                if self.file.get_var(var).constant.is_some() {
+                    self.file.diagnostics.push(Diagnostic::error(format!(
+                        "Cannot assign to `const {}`",
+                        self.interner.get_string(name),
+                    )).with_span(span));
+                }
+                var
+            },
+            None=>{
+                self.file.diagnostics.push(Diagnostic::error(format!(
+                    "Undefined variable `{}`",
+                    self.interner.get_string(name),
+                )).with_span(span));
+                VarIndex::invalid()
+            },
+        }
+    }
+
+    /// The number of values the expression produces, if it is known without type information.
+    fn expr_arity(&self, expr: ExprIndex)->Option<usize> {
+        match self.file.get_expr(expr) {
+            Expr::Group(items)=>Some(items.len()),
+            Expr::Number(_)|Expr::String(_)|Expr::Char(_)|Expr::None=>Some(1),
+            _=>None,
+        }
+    }
+
+    /// Resolves every name use now that all of the functions are converted. Walking out from the
+    /// scope of the use, the first scope that defines the name as either a variable (before the
+    /// use) or a function wins, so functions can be used before they are defined. Names with a
+    /// single definition become an `Expr::Function`, whether they are called or passed around as a
+    /// value, while overloaded names are left to be dispatched by pattern at runtime.
+    fn resolve_names(&mut self) {
+        for (index, scope, span) in std::mem::take(&mut self.unresolved) {
+            let (name, var_scope) = match self.file.get_expr(index) {
+                Expr::RawVar(name)=>(*name, None),
+                Expr::Var(var)=>{
+                    let var = self.file.get_var(*var);
+                    (var.name, Some(var.in_scope))
+                },
+                _=>continue,
+            };
+
+            let mut resolved = var_scope.is_some();
+            let mut replacement = None;
+            let mut current = Some(scope);
+
+            while let Some(index) = current {
+                if var_scope == Some(index) {
+                    break;
+                }
+
+                let scope_ref = self.file.get_scope(index);
+                if let Some(functions) = scope_ref.functions.get(&name) {
+                    let mut all = functions.values().flatten();
+                    replacement = match (all.next(), all.next()) {
+                        (Some(function), None)=>Some(Expr::Function(*function)),
+                        _=>Some(Expr::RawVar(name)),
+                    };
+                    resolved = true;
+                    break;
+                }
+
+                current = scope_ref.parent;
+            }
+
+            if let Some(replacement) = replacement {
+                *self.file.get_mut_expr(index) = replacement;
+            }
+
+            let name_str = self.interner.get_string(name);
+            if !resolved && !BUILTINS.contains(&name_str) {
+                let error = Diagnostic::error(format!("Undefined name `{name_str}`"));
+                self.file.diagnostics.push(error.with_span(span));
+            }
+        }
+    }
+
+    /// Checks that nothing with a known type is applied like a function. There are no function
+    /// types yet, so every known type is not callable.
+    fn check_callable(&mut self) {
+        let mut diagnostics = Vec::new();
+
+        for (_, expr) in self.file.all_exprs() {
+            let left = match expr {
+                Expr::Operation{left, op: Operator::Apply, ..}=>*left,
+                _=>continue,
+            };
+
+            if let Some(ty) = self.known_type(left) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "A value of type `{ty:?}` is not callable",
+                )).with_span_if(self.file.leading_span(left)));
+            }
+        }
+
+        self.file.diagnostics.append(&mut diagnostics);
+    }
+
+    /// Checks the argument count of every call to a resolved function against the arity of its
+    /// pattern. Arguments that are not a group count as a single argument.
+    ///
+    /// Functions are curried, so giving some but not all of the arguments makes an `Expr::Partial`
+    /// that holds them. Applying a partial application right away gives it more arguments, and it
+    /// becomes a normal call once it has all of them. Only too many arguments is an error.
+    fn check_arity(&mut self) {
+        let calls = self.file.all_exprs()
+            .filter(|(_, expr)|matches!(expr, Expr::Operation{op: Operator::Apply, ..}))
+            .map(|(index, _)|index)
+            .collect::<Vec<_>>();
+
+        // applications are converted before the ones they are applied in, so a partial
+        // application is always made before anything applies it
+        for index in calls {
+            let (left, right) = match self.file.get_expr(index) {
+                Expr::Operation{left, right, ..}=>(*left, *right),
+                _=>continue,
+            };
+            let (function, mut args) = match self.file.get_expr(left) {
+                Expr::Function(function)=>(*function, Vec::new()),
+                Expr::Partial{function, args}=>(*function, args.clone()),
+                _=>continue,
+            };
+            let partial = args.len() > 0;
+            match self.file.get_expr(right) {
+                Expr::Group(items)=>args.extend(items),
+                _=>args.push(right),
+            }
+
+            let function_ref = self.file.get_function(function);
+            let expected = function_ref.pattern.arity();
+            let required = expected - function_ref.defaults.len();
+
+            // leaving out defaulted parameters completes the call instead of making it partial
+            let filled = args.len() >= required && args.len() < expected;
+            if filled {
+                let missing = function_ref.defaults[args.len() - required..].to_vec();
+                for default in missing {
+                    args.push(self.file.patch_expr(default, index));
+                }
+            }
+
+            let function_ref = self.file.get_function(function);
+            if args.len() > expected || (args.len() == 0 && expected > 0) {
+                let msg = format!(
+                    "Function `{}` takes {expected} argument(s), but {} were given",
+                    self.interner.get_string(function_ref.name),
+                    args.len(),
+                );
+                let span = self.file.leading_span(left);
+                self.file.diagnostics.push(Diagnostic::error(msg).with_span_if(span));
+            } else if args.len() < expected {
+                // keep the function's name, since the partial replaces the expression it was in
+                if let Some(span) = self.file.leading_span(left) {
+                    self.file.name_spans.insert(index, span);
+                }
+                *self.file.get_mut_expr(index) = Expr::Partial{function, args};
+            } else if partial || filled {
+                // every argument is given now, so it is a normal call
+                *self.file.get_mut_expr(left) = Expr::Function(function);
+                let right = match args.as_slice() {
+                    [arg]=>*arg,
+                    _=>self.file.patch_expr(Expr::Group(args), index),
+                };
+                *self.file.get_mut_expr(index) = Expr::Operation {
+                    left,
+                    right,
+                    op: Operator::Apply,
+                };
+            }
+        }
+    }
+
+    /// Looks up a type written in the source. `context` says where it was written for the error.
+    fn resolve_type(&mut self, name: Name, context: &str)->Type {
+        let name = self.interner.get_string(name);
+
+        match Type::from_name(name) {
+            Some(ty)=>ty,
+            None=>{
+                self.file.diagnostics.push(Diagnostic::error(format!(
+                    "Unknown type `{name}` in {context}",
+                )));
+                Type::Undetermined
+            },
+        }
+    }
+
+    /// The type of the expression, if it is known. Only literals, casts, operators, and immutable
+    /// variables initialized with one have a known type until there is type inference. Comparisons
+    /// and `and`/`or` are always a `Bool`, and borrows are always a reference.
+    fn known_type(&self, expr: ExprIndex)->Option<Type> {
+        match self.file.get_expr(expr) {
+            Expr::Number(_)=>Some(Type::Number),
+            Expr::String(_)=>Some(Type::String),
+            Expr::Char(_)=>Some(Type::Char),
+            Expr::None=>Some(Type::Option(Box::new(Type::Undetermined))),
+            Expr::Group(items)=>items.iter()
+                .map(|item|self.known_type(*item))
+                .collect::<Option<Vec<_>>>()
+                .map(Type::Tuple),
+            // the elements are checked against each other when the list is converted
+            Expr::List(items)=>{
+                let element = items.iter()
+                    .find_map(|item|self.known_type(*item))
+                    .unwrap_or(Type::Undetermined);
+                Some(Type::List(Box::new(element)))
+            },
+            Expr::Var(var)=>match self.file.get_var(*var) {
+                var if var.mutable || var.data_type == Type::Undetermined=>None,
+                var=>Some(var.data_type.clone()),
+            },
+            Expr::Operation{left, right, op}=>match op {
+                Operator::Add|
+                    Operator::Sub|
+                    Operator::Mul|
+                    Operator::Div|
+                    Operator::Rem|
+                    Operator::And|
+                    Operator::Or|
+                    Operator::Xor=>{
+                        let left = self.known_type(*left)?;
+                        let right = self.known_type(*right)?;
+                        (left == Type::Number && right == Type::Number).then_some(Type::Number)
+                    },
+                Operator::Equal|
+                    Operator::NotEqual|
+                    Operator::Less|
+                    Operator::LessEqual|
+                    Operator::Greater|
+                    Operator::GreaterEqual|
+                    Operator::LogicAnd|
+                    Operator::LogicOr=>Some(Type::Bool),
+                Operator::Apply=>None,
+            },
+            Expr::Negate(inner)=>self.known_type(*inner).filter(|ty|*ty == Type::Number),
+            // a borrow is always a reference, even if the type of what it borrows isn't known
+            Expr::Borrow(inner)=>{
+                let inner = self.known_type(*inner).unwrap_or(Type::Undetermined);
+                Some(Type::Ref(Box::new(inner)))
+            },
+            Expr::BorrowMut(inner)=>{
+                let inner = self.known_type(*inner).unwrap_or(Type::Undetermined);
+                Some(Type::RefMut(Box::new(inner)))
+            },
+            Expr::Cast{ty: Type::Undetermined, ..}=>None,
+            Expr::Cast{ty, ..}=>Some(ty.clone()),
+            _=>None,
+        }
+    }
+
+    /// Checks the variable's value against the type written in its `let`, and gives it that type.
+    fn annotate(&mut self, var: VarIndex, ty: Name) {
+        let ty = self.resolve_type(ty, "`let`");
+        let var = self.file.get_mut_var(var);
+
+        if var.data_type != Type::Undetermined && !ty.accepts(&var.data_type) {
+            let msg = format!(
+                "`{}` is declared as `{ty:?}`, but its value is `{:?}`",
+                self.interner.get_string(var.name),
+                var.data_type,
+            );
+            let span = var.span.clone();
+            self.file.diagnostics.push(Diagnostic::error(msg).with_span(span));
+        } else if ty != Type::Undetermined {
+            var.data_type = ty;
+        }
+    }
+
+    /// Checks every `return` in a function with a declared return type against it. A `return`
+    /// without a value returns `()`.
+    fn check_return_types(&mut self) {
+        let mut diagnostics = Vec::new();
+
+        for (_, stmt) in self.file.all_stmts() {
+            let (function, data) = match stmt {
+                Stmt::Return{function, data}=>(self.file.get_function(*function), *data),
+                _=>continue,
+            };
+            let expected = match &function.return_type {
+                Some(Type::Undetermined)|None=>continue,
+                Some(ty)=>ty,
+            };
+
+            let actual = match data {
+                Some(data)=>self.known_type(data),
+                None=>Some(Type::Tuple(Vec::new())),
+            };
+
+            if let Some(actual) = actual.filter(|actual|!expected.accepts(actual)) {
+                // a bare `return` has nothing to point at but the function
+                let span = data.and_then(|data|self.file.leading_span(data))
+                    .unwrap_or_else(||function.span.clone());
+                diagnostics.push(Diagnostic::error(format!(
+                    "Function `{}` is declared to return `{expected:?}`, but returns `{actual:?}`",
+                    self.interner.get_string(function.name),
+                )).with_span(span));
+            }
+        }
+
+        self.file.diagnostics.append(&mut diagnostics);
+    }
+
+    /// Checks that the condition of every `if` and `cond` arm is a `Bool`.
+    fn check_conditions(&mut self) {
+        let mut diagnostics = Vec::new();
+
+        for (_, stmt) in self.file.all_stmts() {
+            let (conditions, construct) = match stmt {
+                Stmt::IfElse{condition, ..}=>(std::slice::from_ref(condition), "an `if`"),
+                Stmt::Conditional{conditions, ..}=>(conditions.as_slice(), "a `cond` arm"),
+                _=>continue,
+            };
+
+            for condition in conditions {
+                match self.known_type(*condition) {
+                    Some(Type::Bool)|None=>{},
+                    Some(ty)=>diagnostics.push(Diagnostic::error(format!(
+                        "The condition of {construct} must be a `Bool`, but `{}` is a `{ty:?}`",
+                        self.file.expr_to_string(*condition, self.interner),
+                    )).with_span_if(self.file.leading_span(*condition))),
+                }
+            }
+        }
+
+        self.file.diagnostics.append(&mut diagnostics);
+    }
+
+    /// Reports the type of every `typeof` expression as a note until there is somewhere to use it.
+    fn resolve_typeof(&mut self) {
+        let mut diagnostics = Vec::new();
+
+        for (_, expr) in self.file.all_exprs() {
+            let inner = match expr {
+                Expr::TypeOf(inner)=>*inner,
+                _=>continue,
+            };
+
+            let shown = self.file.expr_to_string(inner, self.interner);
+            diagnostics.push(match self.known_type(inner) {
+                Some(ty)=>Diagnostic::note(format!("The type of `{shown}` is `{ty:?}`")),
+                None=>Diagnostic::note(format!("The type of `{shown}` is not known yet")),
+            });
+        }
+
+        self.file.diagnostics.append(&mut diagnostics);
+    }
+
+    /// Checks the type of every `assert_type` expression, as far as it is known without type
+    /// inference. An unknown type is only a warning, since it can't be checked either way.
+    fn check_type_assertions(&mut self) {
+        for (expr, ty) in std::mem::take(&mut self.type_assertions) {
+            let shown = self.file.expr_to_string(expr, self.interner);
+            let span = self.file.leading_span(expr);
+            match self.known_type(expr) {
+                Some(found) if found != ty=>self.file.diagnostics.push(Diagnostic::error(format!(
+                    "`assert_type` failed: `{shown}` is a `{found:?}`, not a `{ty:?}`",
+                )).with_span_if(span)),
+                Some(_)=>{},
+                None=>self.file.diagnostics.push(Diagnostic::warning(format!(
+                    "`assert_type` can't check `{shown}`, because its type is not known yet",
+                )).with_span_if(span)),
+            }
+        }
+    }
+
+    /// Turns `.name` on the result of a function with named outputs into an index into the returned
+    /// tuple. The name has to be one of the function's outputs.
+    fn resolve_output_fields(&mut self) {
+        let fields = self.file.all_exprs()
+            .filter_map(|(index, expr)|match expr {
+                Expr::Field{left, name}=>Some((index, *left, *name)),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+
+        for (index, left, name) in fields {
+            let function = match self.output_owner(left) {
+                Some(function)=>self.file.get_function(function),
+                None=>continue,
+            };
+            if function.outputs.len() == 0 {
+                continue;
+            }
+
+            match function.outputs.iter().position(|output|*output == name) {
+                Some(position)=>{
+                    *self.file.get_mut_expr(index) = Expr::TupleIndex {
+                        left,
+                        index: position,
+                    };
+                },
+                None=>{
+                    let msg = format!(
+                        "Function `{}` has no output named `{}`",
+                        self.interner.get_string(function.name),
+                        self.interner.get_string(name),
+                    );
+                    let span = self.file.leading_span(left);
+                    self.file.diagnostics.push(Diagnostic::error(msg).with_span_if(span));
+                },
+            }
+        }
+    }
+
+    /// Finds the top-level `main`. It has to be a single `proc main ()`, since running the
+    /// program has side effects.
+    fn find_entry_point(&mut self) {
+        let name = match self.interner.get_index("main") {
+            Some(name)=>name,
+            None=>return,
+        };
+        let root = self.file.get_scope(self.file.root_scope);
+        let mut definitions = match root.functions.get(&name) {
+            Some(functions)=>functions.values().flatten().copied().collect::<Vec<_>>(),
+            None=>return,
+        };
+
+        if definitions.len() > 1 {
+            let span = self.file.get_function(definitions[1]).span.clone();
+            let error = Diagnostic::error("The entry point `main` can't be overloaded");
+            self.file.diagnostics.push(error.with_span(span));
+            return;
+        }
+        let index = definitions.pop().unwrap();
+        let main = self.file.get_function(index);
+        let span = main.span.clone();
+
+        if !main.is_proc {
+            self.file.diagnostics.push(Diagnostic::error(
+                "The entry point `main` must be a `proc`, since it has side effects",
+            ).with_span(span));
+        } else if main.pattern.arity() != 0 || main.guard.is_some() {
+            self.file.diagnostics.push(Diagnostic::error(
+                "The entry point `main` must take no arguments, like `proc main ()`",
+            ).with_span(span));
+        } else {
+            self.file.main = Some(index);
+        }
+    }
+
+    /// The function whose result the expression is, either a call or a variable that is never
+    /// reassigned after being initialized with one.
+    fn output_owner(&self, expr: ExprIndex)->Option<FunctionIndex> {
+        match self.file.get_expr(expr) {
+            Expr::Operation{op: Operator::Apply, ..}=>{
+                match self.file.get_expr(callee(&self.file, expr)) {
+                    Expr::Function(function)=>Some(*function),
+                    _=>None,
+                }
+            },
+            Expr::Var(var)=>{
+                let var = self.file.get_var(*var);
+                if var.mutable {
+                    return None;
+                }
+                self.output_owner(var.init?)
+            },
+            _=>None,
+        }
+    }
+
     fn next_stmt_index(&self)->StmtIndex {
         StmtIndex {
             root: self.file.stmts.len(),
@@ -63,6 +606,23 @@ impl FileConversion {
                 let left = self.convert_expr(*left);
                 self.file.add_expr(Expr::Field{left, name})
             },
+            PExpr::Cast{expr, ty}=>{
+                let expr = self.convert_expr(*expr);
+                let ty = self.resolve_type(ty, "cast");
+
+                if let Some(from) = self.known_type(expr) {
+                    let legal = ty.accepts(&from) || (from.is_numeric() && ty.is_numeric());
+                    if !legal && ty != Type::Undetermined {
+                        self.file.diagnostics.push(Diagnostic::error(format!(
+                            "Cannot cast `{from:?}` to `{ty:?}`",
+                        )));
+                    }
+                }
+
+                self.file.add_expr(Expr::Cast{expr, ty})
+            },
+            PExpr::Paren(inner)=>self.convert_expr(*inner),
+            // The parser already drops redundant parentheses, so a single item here is a 1-tuple
             PExpr::Group(list)=>{
                 let new_list = list
                     .into_iter()
@@ -70,93 +630,333 @@ impl FileConversion {
                     .collect::<Vec<_>>();
                 self.file.add_expr(Expr::Group(new_list))
             },
-            PExpr::Var(name)=>self.file.add_expr(Expr::RawVar(name)),
-            PExpr::Number(n)=>self.file.add_expr(Expr::Number(n)),
+            PExpr::List(items)=>{
+                let items = items
+                    .into_iter()
+                    .map(|e|self.convert_expr(e))
+                    .collect::<Vec<_>>();
+
+                if let Some(first) = items.iter().find_map(|item|self.known_type(*item)) {
+                    let other = items.iter()
+                        .filter_map(|item|Some((*item, self.known_type(*item)?)))
+                        .find(|(_, ty)|!ty.accepts(&first) && !first.accepts(ty));
+                    if let Some((item, other)) = other {
+                        self.file.diagnostics.push(Diagnostic::error(format!(
+                            "List elements have different types: `{first:?}` and `{other:?}`",
+                        )).with_span_if(self.file.leading_span(item)));
+                    }
+                }
+
+                self.file.add_expr(Expr::List(items))
+            },
+            PExpr::Var(name, span)=>{
+                let index = match self.lookup_var(name) {
+                    Some(var)=>self.file.add_expr(Expr::Var(var)),
+                    None=>self.file.add_expr(Expr::RawVar(name)),
+                };
+                self.file.name_spans.insert(index, span.clone());
+                self.unresolved.push((index, self.current_scope, span));
+                index
+            },
+            PExpr::Number(n, _)=>self.file.add_expr(Expr::Number(n)),
             PExpr::String(s)=>self.file.add_expr(Expr::String(s)),
+            PExpr::Char(c)=>self.file.add_expr(Expr::Char(c)),
             PExpr::Borrow(inner)=>{
                 let inner = self.convert_expr(*inner);
+
+                if let Expr::Var(var) = self.file.get_expr(inner) {
+                    let site = self.next_stmt_index();
+                    self.file.get_mut_var(*var).borrows.push(site);
+                }
+
                 self.file.add_expr(Expr::Borrow(inner))
             },
+            PExpr::BorrowMut(inner)=>{
+                let inner = self.convert_expr(*inner);
+
+                if let Expr::Var(var) = *self.file.get_expr(inner) {
+                    let site = self.next_stmt_index();
+                    let var = self.file.get_mut_var(var);
+                    var.mut_borrows.push(site);
+
+                    if !var.mutable {
+                        let msg = format!(
+                            "Cannot mutably borrow `{}` because it is not declared `mut`",
+                            self.interner.get_string(var.name),
+                        );
+                        self.file.diagnostics.push(Diagnostic::error(msg));
+                    }
+                }
+
+                self.file.add_expr(Expr::BorrowMut(inner))
+            },
             PExpr::Deref(inner)=>{
                 let inner = self.convert_expr(*inner);
                 self.file.add_expr(Expr::Deref(inner))
             },
+            PExpr::Negate(inner)=>{
+                let inner = self.convert_expr(*inner);
+                self.file.add_expr(Expr::Negate(inner))
+            },
+            PExpr::Heap(inner)=>{
+                let inner = self.convert_expr(*inner);
+                self.file.add_expr(Expr::Heap(inner))
+            },
+            PExpr::Stack(inner)=>{
+                let inner = self.convert_expr(*inner);
+                self.file.add_expr(Expr::Stack(inner))
+            },
+            PExpr::TypeOf(inner)=>{
+                let inner = self.convert_expr(*inner);
+                self.file.add_expr(Expr::TypeOf(inner))
+            },
+            // lambdas are converted with the other functions and the placeholder is patched after
+            PExpr::Lambda{name, param, span, body}=>{
+                let index = self.file.add_expr(Expr::Function(FunctionIndex::invalid()));
+                self.raw_func_queue.push(RawFunction {
+                    owning_scope: self.current_scope,
+                    is_proc: false,
+                    name,
+                    span: span.clone(),
+                    pattern: Pattern::Name(param, span),
+                    defaults: Vec::new(),
+                    return_type: None,
+                    guard: None,
+                    doc: None,
+                    block: PBlock(vec![PStmt::Expr(*body, false)]),
+                    lambda: Some(index),
+                });
+                index
+            },
+            PExpr::Scope{..}=>{
+                self.file.diagnostics.push(Diagnostic::error(
+                    "A `scope` that gives a value can only be bound to a name with `let`",
+                ));
+                self.file.add_expr(Expr::None)
+            },
             PExpr::None=>self.file.add_expr(Expr::None),
         }
     }
 
     fn convert_stmt(&mut self, scope: ScopeIndex, expr: PStmt)->StmtReturn {
         match expr {
-            PStmt::FunctionDef{is_proc, name, pattern, block}=>StmtReturn {
+            PStmt::FunctionDef{
+                is_proc,
+                name,
+                span,
+                pattern,
+                defaults,
+                return_type,
+                guard,
+                doc,
+                block,
+                ..
+            }=>StmtReturn {
                 function: Some(RawFunction {
                     owning_scope: scope,
                     is_proc,
                     name,
+                    span,
                     pattern,
+                    defaults,
+                    return_type,
+                    guard,
+                    doc,
                     block,
+                    lambda: None,
                 }),
                 scopes: Vec::new(),
-                var: None,
+                vars: Vec::new(),
             },
-            PStmt::VarDef{mutable, name, data}=>{
+            PStmt::VarDef{
+                mutable,
+                pattern: Pattern::Name(name, span),
+                ty,
+                data: Some(PExpr::Scope{label, block}),
+            } if !self.is_discard(name)=>{
+                let ret = self.convert_scope_value(scope, mutable, name, span, label, block);
+                if let Some(ty) = ty {
+                    self.annotate(ret.vars[0].1, ty);
+                }
+
+                ret
+            },
+            PStmt::VarDef{mutable, pattern, ty, data}=>{
                 let mut data_index = None;
                 if let Some(data) = data {
                     data_index = Some(self.convert_expr(data));
                 }
 
-                let def = self.file.add_var(VarMetadata {
-                    in_scope: scope,
+                let mut vars = Vec::new();
 
-                    definition: self.this_stmt_index(),
-                    init: data_index,
-                    disown: None,
+                let single = matches!(&pattern, Pattern::Name(name, _) if !self.is_discard(*name));
+                if ty.is_some() && !single {
+                    self.file.diagnostics.push(Diagnostic::error(
+                        "Only a `let` of a single name can have a type",
+                    ));
+                }
 
-                    data_type: Type::Undetermined,
+                match (pattern, data_index) {
+                    // `let _ = data` only evaluates `data`
+                    (Pattern::Name(name, _), Some(data)) if self.is_discard(name)=>{
+                        let index = self.file.add_stmt(Stmt::Expr(data));
+                        self.file.discarded.insert(index);
+                    },
+                    (Pattern::Name(name, _), None) if self.is_discard(name)=>{
+                        self.file.diagnostics.push(Diagnostic::error(
+                            "`let _` requires an initializer",
+                        ));
+                    },
+                    (Pattern::Name(name, span), _)=>{
+                        let def = self.file.add_var(VarMetadata::new(
+                            scope,
+                            name,
+                            mutable,
+                            self.next_stmt_index(),
+                            data_index,
+                            span,
+                        ));
 
-                    borrows: Vec::new(),
-                    uses: Vec::new(),
-                    derefs: Vec::new(),
-                    assigns: Vec::new(),
+                        // `let x = heap data` keeps `x` on the heap, whatever the analysis decides,
+                        // and `let x = stack data` makes it an error for the analysis to hoist it
+                        match data_index.map(|data|self.file.get_expr(data)) {
+                            Some(Expr::Heap(_))=>{
+                                self.file.get_mut_var(def).mem_loc = MemoryLocation::Heap;
+                            },
+                            Some(Expr::Stack(_))=>self.file.get_mut_var(def).stack_pinned = true,
+                            _=>{},
+                        }
+                        if let Some(ty) = data_index.and_then(|data|self.known_type(data)) {
+                            self.file.get_mut_var(def).data_type = ty;
+                        }
+                        if let Some(ty) = ty {
+                            self.annotate(def, ty);
+                        }
 
-                    mem_loc: MemoryLocation::Undetermined,
+                        self.file.add_stmt(Stmt::VarDef(def));
+                        vars.push((name, def));
+                    },
+                    (pattern, Some(data))=>{
+                        self.bind_let_pattern(scope, &pattern, mutable, data, &mut vars);
 
-                    mutable,
+                        self.file.add_stmt(Stmt::Destructure {
+                            pattern: Rc::new(pattern),
+                            data,
+                            vars: vars.iter().map(|(_, var)|*var).collect(),
+                        });
+                    },
+                    (_, None)=>self.file.diagnostics.push(Diagnostic::error(
+                        "Destructuring `let` requires an initializer",
+                    )),
+                }
+
+                StmtReturn {
+                    vars,
+                    function: None,
+                    scopes: Vec::new(),
+                }
+            },
+            // a `const` is a variable that is always inlined, and its value is kept in the file's
+            // constant table
+            PStmt::Const{name, span, data}=>{
+                let data = self.convert_expr(data);
+                let def = self.file.add_var(VarMetadata::new(
+                    scope,
                     name,
-                });
+                    false,
+                    self.next_stmt_index(),
+                    Some(data),
+                    span.clone(),
+                ));
+
+                match fold(&self.file, data) {
+                    Some(constant)=>{
+                        let var = self.file.get_mut_var(def);
+                        var.constant = Some(constant);
+                        var.mem_loc = MemoryLocation::Inlined;
+                        if let Some(ty) = self.known_type(data) {
+                            self.file.get_mut_var(def).data_type = ty;
+                        }
+
+                        if self.file.constants.insert(name, constant).is_some() {
+                            self.file.diagnostics.push(Diagnostic::error(format!(
+                                "`const {}` is defined more than once",
+                                self.interner.get_string(name),
+                            )).with_span(span));
+                        }
+                    },
+                    None=>self.file.diagnostics.push(Diagnostic::error(format!(
+                        "The value of `const {}` must be known at compile time, but `{}` is not",
+                        self.interner.get_string(name),
+                        self.file.expr_to_string(data, self.interner),
+                    )).with_span(self.file.leading_span(data).unwrap_or(span))),
+                }
 
                 self.file.add_stmt(Stmt::VarDef(def));
 
                 StmtReturn {
-                    var: Some((name, def)),
+                    vars: vec![(name, def)],
                     function: None,
                     scopes: Vec::new(),
                 }
             },
-            PStmt::VarSet{name, data}=>{
+            PStmt::VarSet{name, span, data}=>{
                 let data = self.convert_expr(data);
+                let var = self.assign_target(name, span.clone());
 
                 self.file.add_stmt(Stmt::VarSet{
                     name,
+                    span: Some(span),
+                    data,
+                    var,
+                });
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: Vec::new(),
+                }
+            },
+            PStmt::VarSetMulti{targets, spans, data}=>{
+                let data = self.convert_expr(data);
+                let vars = targets
+                    .iter()
+                    .zip(&spans)
+                    .map(|(name, span)|self.assign_target(*name, span.clone()))
+                    .collect::<Vec<_>>();
+
+                if let Some(arity) = self.expr_arity(data) {
+                    if arity != targets.len() {
+                        self.file.diagnostics.push(Diagnostic::error(format!(
+                            "Cannot assign {arity} value(s) to {} targets",
+                            targets.len(),
+                        )));
+                    }
+                }
+
+                self.file.add_stmt(Stmt::VarSetMulti {
+                    names: targets,
+                    spans,
                     data,
-                    var: VarIndex::invalid(),
+                    vars,
                 });
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes: Vec::new(),
                 }
             },
-            PStmt::IfElse{condition, block, default}=>{
+            PStmt::IfElse{condition, block, default, span}=>{
                 let mut scopes = Vec::new();
 
                 let condition = self.convert_expr(condition);
 
-                let block = self.convert_block(block);
+                let block = self.convert_block(Some(scope), block);
                 scopes.push(block.scope);
 
                 let else_block = if let Some(else_block) = default {
-                    let block = self.convert_block(else_block);
+                    let block = self.convert_block(Some(scope), else_block);
                     scopes.push(block.scope);
                     Some(block)
                 } else {None};
@@ -167,15 +967,16 @@ impl FileConversion {
                     block,
                     else_block,
                     last: self.this_stmt_index(),
+                    span,
                 });
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes,
                 }
             },
-            PStmt::Conditional{conditions, actions}=>{
+            PStmt::Conditional{conditions, actions, default, span}=>{
                 let mut scopes = Vec::new();
                 let conditions = conditions
                     .into_iter()
@@ -183,37 +984,62 @@ impl FileConversion {
                     .collect::<Vec<_>>();
                 let actions = actions
                     .into_iter()
-                    .map(|act|match act {
-                        PCondAct::Expr(e)=>ConditionalAction::Expr(self.convert_expr(e)),
-                        PCondAct::Scope(block)=>{
-                            let block = self.convert_block(block);
-                            scopes.push(block.scope);
-                            ConditionalAction::Scope(block)
-                        },
-                    })
+                    .map(|act|self.convert_cond_action(scope, act, &mut scopes))
                     .collect::<Vec<_>>();
+                let default = default.map(|act|self.convert_cond_action(scope, act, &mut scopes));
 
                 self.file.add_stmt(Stmt::Conditional{
                     conditions,
                     actions,
+                    default,
                     last: self.this_stmt_index(),
+                    span,
                 });
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes,
                 }
             },
-            PStmt::Scope(block)=>{
-                let block = self.convert_block(block);
+            PStmt::Scope{label, block}=>{
+                let (block, _) = self.convert_scope(scope, label, block, None);
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes: vec![block.scope],
                 }
             },
+            // a `loop` is a `scope` that jumps back to its start at the end
+            PStmt::Loop{label, mut block, span}=>{
+                block.0.push(PStmt::Continue(label));
+                let (block, _) = self.convert_scope(scope, label, block, None);
+                self.file.get_mut_scope(block.scope).is_loop = true;
+
+                if !self.leaves_block(&block) {
+                    self.endless_loops.push(span);
+                }
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: vec![block.scope],
+                }
+            },
+            PStmt::AssertType{expr, ty}=>{
+                let expr = self.convert_expr(expr);
+                let ty = self.resolve_type(ty, "`assert_type`");
+                if ty != Type::Undetermined {
+                    self.type_assertions.push((expr, ty));
+                }
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: Vec::new(),
+                }
+            },
             PStmt::Disown(e)=>{
                 let expr = self.convert_expr(e);
 
@@ -221,7 +1047,7 @@ impl FileConversion {
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes: Vec::new(),
                 }
             },
@@ -231,37 +1057,243 @@ impl FileConversion {
                     expr = Some(self.convert_expr(e));
                 }
 
-                self.file.add_stmt(Stmt::Return(expr));
+                match self.function_stack.last() {
+                    Some(&function)=>{
+                        self.file.add_stmt(Stmt::Return{function, data: expr});
+                    },
+                    None=>self.file.diagnostics.push(Diagnostic::error(
+                        "`return` outside of a function",
+                    )),
+                }
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: Vec::new(),
+                }
+            },
+            PStmt::Break(label, value)=>{
+                let value = value.map(|value|self.convert_expr(value));
+
+                let position = match label {
+                    Some(label)=>self.breakables
+                        .iter()
+                        .rposition(|b|b.label == Some(label)),
+                    None=>self.breakables.len().checked_sub(1),
+                };
+
+                if let (Some(position), Some(value)) = (position, value) {
+                    match self.breakables[position].result {
+                        Some(var)=>{
+                            let name = self.file.get_var(var).name;
+                            self.file.add_stmt(Stmt::VarSet{name, span: None, data: value, var});
+                            self.breakables[position].values.push(value);
+                        },
+                        None=>self.file.diagnostics.push(Diagnostic::error(
+                            "`break` with a value can only leave a `scope` that gives a value",
+                        )),
+                    }
+                }
+
+                let index = self.file.add_stmt(Stmt::JumpTo(StmtIndex::invalid()));
+
+                match (position, label) {
+                    (Some(position), _)=>self.breakables[position].breaks.push(index),
+                    (None, Some(label))=>self.file.diagnostics.push(Diagnostic::error(format!(
+                        "Undefined label `'{}` for `break`",
+                        self.interner.get_string(label),
+                    ))),
+                    (None, None)=>self.file.diagnostics.push(Diagnostic::error(
+                        "`break` outside of a `scope`",
+                    )),
+                }
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes: Vec::new(),
                 }
             },
-            PStmt::Expr(e)=>{
+            PStmt::Continue(label)=>{
+                let breakable = match label {
+                    Some(label)=>self.breakables
+                        .iter()
+                        .rev()
+                        .find(|b|b.label == Some(label)),
+                    None=>self.breakables.last(),
+                };
+
+                match (breakable, label) {
+                    (Some(breakable), _)=>{
+                        self.file.add_stmt(Stmt::JumpTo(breakable.start));
+                    },
+                    (None, Some(label))=>self.file.diagnostics.push(Diagnostic::error(format!(
+                        "Undefined label `'{}` for `continue`",
+                        self.interner.get_string(label),
+                    ))),
+                    (None, None)=>self.file.diagnostics.push(Diagnostic::error(
+                        "`continue` outside of a `scope`",
+                    )),
+                }
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: Vec::new(),
+                }
+            },
+            PStmt::Cfg{feature, stmt}=>{
+                if self.features.contains(&self.interner.get_string(feature)) {
+                    return self.convert_stmt(scope, *stmt);
+                }
+
+                StmtReturn {
+                    function: None,
+                    vars: Vec::new(),
+                    scopes: Vec::new(),
+                }
+            },
+            PStmt::Expr(e, _)=>{
                 let expr = self.convert_expr(e);
 
                 self.file.add_stmt(Stmt::Expr(expr));
 
                 StmtReturn {
                     function: None,
-                    var: None,
+                    vars: Vec::new(),
                     scopes: Vec::new(),
                 }
             },
         }
     }
 
-    fn convert_block(&mut self, PBlock(stmts): PBlock)->Block {
-        let scope_index = self.file.add_scope(Scope {
-            first: self.next_stmt_index(),
-            last: self.next_stmt_index(),
-            functions: FnvHashMap::default(),
-            scopes: Vec::new(),
-            stack_slots: 0,
-            vars: FnvHashMap::default(),
+    /// Converts the action of a `cond` arm. Nested `cond`s are put in their own scope.
+    fn convert_cond_action(
+        &mut self,
+        scope: ScopeIndex,
+        action: PCondAct,
+        scopes: &mut Vec<ScopeIndex>,
+    )->ConditionalAction {
+        match action {
+            PCondAct::Expr(e)=>ConditionalAction::Expr(self.convert_expr(e)),
+            PCondAct::Scope(block)=>{
+                let block = self.convert_block(Some(scope), block);
+                scopes.push(block.scope);
+                ConditionalAction::Scope(block)
+            },
+            PCondAct::Cond(cond)=>{
+                let block = self.convert_block(Some(scope), PBlock(vec![*cond]));
+                scopes.push(block.scope);
+                ConditionalAction::Scope(block)
+            },
+        }
+    }
+
+    fn new_scope(&mut self, parent: Option<ScopeIndex>)->ScopeIndex {
+        self.file.add_scope(Scope::new(parent, self.next_stmt_index(), self.next_stmt_index()))
+    }
+
+    #[inline]
+    /// Converts the block of a `scope`, pointing every `break` out of it past its end. Returns the
+    /// values given by the `break`s, if the `scope` gives a value in `result`.
+    fn convert_scope(
+        &mut self,
+        scope: ScopeIndex,
+        label: Option<Name>,
+        block: PBlock,
+        result: Option<VarIndex>,
+    )->(Block, Vec<ExprIndex>) {
+        self.breakables.push(Breakable {
+            label,
+            start: self.next_stmt_index(),
+            breaks: Vec::new(),
+            result,
+            values: Vec::new(),
         });
+        let block = self.convert_block(Some(scope), block);
+        let breakable = self.breakables.pop().unwrap();
+
+        self.file.get_mut_scope(block.scope).label = label;
+
+        // every `break` targeting this scope jumps to the first statement after it
+        let target = self.next_stmt_index();
+        for index in breakable.breaks {
+            *self.file.get_mut_stmt(index) = Stmt::JumpTo(target);
+        }
+
+        return (block, breakable.values);
+    }
+
+    /// Checks if a `break`, `continue`, or `return` in the block can leave it. Functions defined
+    /// in the block are converted later, so their statements aren't in it yet.
+    fn leaves_block(&self, block: &Block)->bool {
+        let range = block.first.root..block.last.root.wrapping_add(1);
+
+        range.clone()
+            .flat_map(|root|self.file.stmt_with_patches(root))
+            .any(|(_, stmt)|match stmt {
+                Stmt::Return{..}=>true,
+                Stmt::JumpTo(target)=>!range.contains(&target.root),
+                _=>false,
+            })
+    }
+
+    /// Converts `let name = scope 'label` into a bare `let` that every `break` out of the `scope`
+    /// assigns before it jumps. Running off the end of the block breaks with its last expression.
+    /// The variable only gets a type if every `break` gives the same one.
+    fn convert_scope_value(
+        &mut self,
+        scope: ScopeIndex,
+        mutable: bool,
+        name: Name,
+        span: Range<usize>,
+        label: Name,
+        PBlock(mut stmts): PBlock,
+    )->StmtReturn {
+        let def = self.file.add_var(VarMetadata::new(
+            scope,
+            name,
+            mutable,
+            self.next_stmt_index(),
+            None,
+            span,
+        ));
+        self.file.add_stmt(Stmt::VarDef(def));
+
+        match stmts.pop() {
+            Some(PStmt::Expr(expr, false))=>stmts.push(PStmt::Break(Some(label), Some(expr))),
+            Some(stmt)=>stmts.push(stmt),
+            None=>{},
+        }
+
+        let (block, values) = self.convert_scope(scope, Some(label), PBlock(stmts), Some(def));
+
+        let mut types = values.iter().filter_map(|value|self.known_type(*value));
+        if let Some(first) = types.next() {
+            match types.find(|ty|!ty.accepts(&first) && !first.accepts(ty)) {
+                Some(other)=>self.file.diagnostics.push(Diagnostic::error(format!(
+                    "The `break`s out of `'{}` give different types: `{first:?}` and `{other:?}`",
+                    self.interner.get_string(label),
+                ))),
+                None=>self.file.get_mut_var(def).data_type = first,
+            }
+        }
+
+        StmtReturn {
+            vars: vec![(name, def)],
+            function: None,
+            scopes: vec![block.scope],
+        }
+    }
+
+    fn convert_block(&mut self, parent: Option<ScopeIndex>, block: PBlock)->Block {
+        let scope_index = self.new_scope(parent);
+        self.convert_block_in(scope_index, block)
+    }
+
+    /// Converts the block's statements into an already created scope.
+    fn convert_block_in(&mut self, scope_index: ScopeIndex, PBlock(stmts): PBlock)->Block {
+        let old_scope = std::mem::replace(&mut self.current_scope, scope_index);
         let first = self.next_stmt_index();
 
         let mut functions = Vec::new();
@@ -269,7 +1301,7 @@ impl FileConversion {
         for stmt in stmts {
             let mut ret = self.convert_stmt(scope_index, stmt);
 
-            if let Some((name, index)) = ret.var {
+            for (name, index) in ret.vars {
                 self.file.scopes[scope_index.0]
                     .vars
                     .entry(name)
@@ -286,7 +1318,11 @@ impl FileConversion {
                 .append(&mut ret.scopes);
         }
 
-        let last = self.this_stmt_index();
+        // an empty block ends just before it starts
+        let last = StmtIndex {
+            root: self.file.stmts.len().wrapping_sub(1),
+            patch: 0,
+        };
         self.file.scopes[scope_index.0].last = last;
 
         let block = Block {
@@ -297,45 +1333,935 @@ impl FileConversion {
 
         self.raw_func_queue.append(&mut functions);
 
+        self.current_scope = old_scope;
+
         return block;
     }
 
-    fn convert_function(&mut self, func: RawFunction) {
+    /// Binds every name in the pattern as a variable in the given scope.
+    fn bind_pattern(&mut self, scope: ScopeIndex, pattern: &Pattern, vars: &mut Vec<VarIndex>) {
+        match pattern {
+            Pattern::Group(items)=>for item in items {
+                self.bind_pattern(scope, item, vars);
+            },
+            Pattern::Name(name, span)=>{
+                let var = self.file.add_var(VarMetadata::new(
+                    scope,
+                    *name,
+                    false,
+                    self.next_stmt_index(),
+                    None,
+                    span.clone(),
+                ));
+                self.file.get_mut_scope(scope)
+                    .vars
+                    .entry(*name)
+                    .or_default()
+                    .push(var);
+                vars.push(var);
+            },
+            Pattern::Number(_)|Pattern::None=>{},
+        }
+    }
+
+    /// Binds every name in a `let` pattern, with each variable initialized by projecting its part
+    /// out of `data`.
+    fn bind_let_pattern(
+        &mut self,
+        scope: ScopeIndex,
+        pattern: &Pattern,
+        mutable: bool,
+        data: ExprIndex,
+        vars: &mut Vec<(Name, VarIndex)>,
+    ) {
+        match pattern {
+            Pattern::Group(items)=>{
+                if let Some(arity) = self.expr_arity(data) {
+                    if arity != items.len() {
+                        self.file.diagnostics.push(Diagnostic::error(format!(
+                            "Cannot destructure {arity} value(s) into a pattern of {}",
+                            items.len(),
+                        )).with_span_if(self.file.leading_span(data)));
+                        return;
+                    }
+                }
+
+                for (index, item) in items.iter().enumerate() {
+                    // project directly out of tuple literals instead of indexing them
+                    let part = match self.file.get_expr(data) {
+                        Expr::Group(elements)=>elements[index],
+                        _=>self.file.add_expr(Expr::TupleIndex {
+                            left: data,
+                            index,
+                        }),
+                    };
+
+                    self.bind_let_pattern(scope, item, mutable, part, vars);
+                }
+            },
+            Pattern::Name(name, _) if self.is_discard(*name)=>{},
+            Pattern::Name(name, span)=>{
+                let var = self.file.add_var(VarMetadata::new(
+                    scope,
+                    *name,
+                    mutable,
+                    self.next_stmt_index(),
+                    Some(data),
+                    span.clone(),
+                ));
+                vars.push((*name, var));
+            },
+            Pattern::Number(_)|Pattern::None=>self.file.diagnostics.push(Diagnostic::error(
+                "Refutable pattern in `let`",
+            )),
+        }
+    }
+
+    fn convert_function(&mut self, mut func: RawFunction) {
+        match func.block.0.pop() {
+            // a `func`'s trailing expression is its return value, unless it ends with `;`
+            Some(PStmt::Expr(expr, false)) if !func.is_proc=>{
+                func.block.0.push(PStmt::Return(Some(expr)));
+            },
+            Some(stmt @ PStmt::Expr(_, true)) if !func.is_proc=>{
+                func.block.0.push(stmt);
+                func.block.0.push(PStmt::Return(None));
+            },
+            Some(stmt)=>func.block.0.push(stmt),
+            // an empty body returns unit
+            None=>func.block.0.push(PStmt::Return(None)),
+        }
+
+        let scope = self.new_scope(Some(func.owning_scope));
+
+        let mut params = Vec::new();
+        self.bind_pattern(scope, &func.pattern, &mut params);
+
+        let old_scope = std::mem::replace(&mut self.current_scope, scope);
+        let guard = func.guard.map(|guard|self.convert_expr(guard));
+
+        // defaults are copied into the calls, so they have to be literals. They are converted where
+        // the function is defined, so they can name `const`s but not the parameters.
+        self.current_scope = func.owning_scope;
+        let mut defaults = Vec::new();
+        for default in func.defaults {
+            let default = self.convert_expr(default);
+            match fold(&self.file, default).and_then(|constant|constant.to_expr()) {
+                Some(literal)=>defaults.push(literal),
+                None=>self.file.diagnostics.push(Diagnostic::error(format!(
+                    "The default value `{}` of a parameter of `{}` must be a constant number, \
+                        string, char, or `None`",
+                    self.file.expr_to_string(default, self.interner),
+                    self.interner.get_string(func.name),
+                )).with_span_if(self.file.leading_span(default))),
+            }
+        }
+        self.current_scope = old_scope;
+
         let pattern = Rc::new(func.pattern);
-        let block = self.convert_block(func.block);
+        let (return_type, outputs) = match func.return_type {
+            Some(PReturnType::Type(ty))=>(Some(self.resolve_type(ty, "return type")), Vec::new()),
+            Some(PReturnType::Named(named))=>{
+                let mut outputs = Vec::new();
+                let mut types = Vec::new();
+                for (name, ty) in named {
+                    if outputs.contains(&name) {
+                        self.file.diagnostics.push(Diagnostic::error(format!(
+                            "Duplicate output `{}` in return type",
+                            self.interner.get_string(name),
+                        )));
+                    }
+                    outputs.push(name);
+                    types.push(self.resolve_type(ty, "return type"));
+                }
+
+                (Some(Type::Tuple(types)), outputs)
+            },
+            None=>(None, Vec::new()),
+        };
+
+        // the function is added right after its body, so this is the index it will get
+        self.function_stack.push(FunctionIndex(self.file.functions.len()));
+        self.endless_loops.clear();
+        let block = self.convert_block_in(scope, func.block);
+        self.function_stack.pop();
+
+        // a `proc` can run forever for its effects, but a `func` is only run for its value
+        if let (false, Some(endless)) = (func.is_proc, self.endless_loops.first()) {
+            self.file.diagnostics.push(Diagnostic::warning(format!(
+                "`func {}` never returns, because nothing leaves the `loop` on line {}",
+                self.interner.get_string(func.name),
+                self.file.line_of(endless.start),
+            )).with_span(func.span.clone()));
+        }
 
         let index = self.file.add_function(FunctionDef {
             is_proc: func.is_proc,
             name: func.name,
+            span: func.span,
             pattern: pattern.clone(),
+            params,
+            defaults,
+            return_type,
+            outputs,
+            guard,
+            doc: func.doc,
             block,
         });
 
-        self.file
-            .get_mut_scope(func.owning_scope)
+        // lambdas can only be called through their expression, so they aren't dispatched by name
+        if let Some(expr) = func.lambda {
+            *self.file.get_mut_expr(expr) = Expr::Function(index);
+            return;
+        }
+
+        let functions = &self.file.functions;
+        let dispatch = self.file.scopes[func.owning_scope.0]
             .functions
             .entry(func.name)
             .or_default()
-            .insert(pattern, index);
+            .entry(pattern)
+            .or_default();
+
+        // guarded definitions are tried before the unguarded fallback
+        if guard.is_some() {
+            let position = dispatch
+                .iter()
+                .position(|f|functions[f.0].guard.is_none())
+                .unwrap_or(dispatch.len());
+            dispatch.insert(position, index);
+        } else if let Some(existing) = dispatch.iter().find(|f|functions[f.0].guard.is_none()) {
+            // only guarded definitions can share a pattern
+            let existing = functions[existing.0].span.start;
+            let msg = format!(
+                "Duplicate definition of `{}`: it has the same pattern as the one on line {}",
+                self.interner.get_string(func.name),
+                self.file.line_of(existing),
+            );
+            let span = self.file.functions[index.0].span.clone();
+            self.file.diagnostics.push(Diagnostic::error(msg).with_span(span));
+        } else {
+            dispatch.push(index);
+        }
     }
 }
 
 struct StmtReturn {
     function: Option<RawFunction>,
-    var: Option<(Name, VarIndex)>,
+    vars: Vec<(Name, VarIndex)>,
     scopes: Vec<ScopeIndex>,
 }
 
+struct Breakable {
+    label: Option<Name>,
+    /// The first statement of the scope, where a `continue` jumps back to.
+    start: StmtIndex,
+    /// The `JumpTo` statements that need to be pointed past the end of the scope.
+    breaks: Vec<StmtIndex>,
+    /// The variable that a `scope` expression's value is put in.
+    result: Option<VarIndex>,
+    /// The values given by the `break`s out of a `scope` expression.
+    values: Vec<ExprIndex>,
+}
+
 struct RawFunction {
     pub owning_scope: ScopeIndex,
     pub is_proc: bool,
     pub name: Name,
+    pub span: Range<usize>,
     pub pattern: Pattern,
+    pub defaults: Vec<PExpr>,
+    pub return_type: Option<PReturnType>,
+    pub guard: Option<PExpr>,
+    pub doc: Option<String>,
     pub block: PBlock,
+    /// The placeholder expression of an anonymous function, which is pointed at it once converted.
+    pub lambda: Option<ExprIndex>,
 }
 
 
 #[inline]
-pub fn convert_parse_tree(stmts: Vec<PStmt>)->File {
-    FileConversion::convert(stmts)
+/// Converts the parse tree of `source`, keeping the statements marked with `#[cfg(feature)]` for
+/// the given features and dropping the rest.
+pub fn convert_parse_tree<'a>(
+    stmts: Vec<PStmt>,
+    source: &str,
+    interner: &'a StringInterner<'a>,
+    features: &'a [&'a str],
+)->File {
+    FileConversion::convert(stmts, source, interner, features)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Every diagnostic from running the whole pipeline on the source.
+    fn diagnostics(source: &str)->Vec<Diagnostic> {
+        match File::parse(source) {
+            Ok((file, _))=>file.diagnostics,
+            Err(ProgramError::Diagnostics(diagnostics))=>diagnostics,
+            Err(ProgramError::Parse(_))=>panic!("`{source}` should parse"),
+        }
+    }
+
+    /// Converts the source without running any of the later passes.
+    fn convert(source: &str)->(File, StringInterner<'_>) {
+        let (stmts, interner, _) = Parser::new(source).parse_all().unwrap();
+        let file = convert_parse_tree(stmts, source, &interner, &[]);
+
+        return (file, interner);
+    }
+
+    /// The var with the given name.
+    fn var_named(file: &File, interner: &StringInterner, name: &str)->VarIndex {
+        let index = file.vars.iter()
+            .position(|var|interner.get_string(var.name) == name)
+            .unwrap();
+
+        return VarIndex(index);
+    }
+
+    /// The statements of the function's body.
+    fn body<'a>(file: &'a File, function: usize)->Vec<&'a Stmt> {
+        file.block_stmts(&file.functions[function].block)
+            .map(|(_, stmt)|stmt)
+            .collect()
+    }
+
+    #[test]
+    fn breaks_leave_labeled_outer_scopes() {
+        let source = "scope 'outer\n    loop\n        break 'outer\nlet after = 1\n";
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors());
+
+        let after = file.get_var(var_named(&file, &interner, "after")).definition;
+        let jumps = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::JumpTo(target)=>Some(*target),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+        // the `break`, and the jump at the end of the loop back to its start
+        assert_eq!(jumps.len(), 2);
+        assert!(jumps.contains(&after));
+
+        let undefined = diagnostics("scope 'outer\n    break 'inner\n");
+        assert!(undefined.iter().any(|d|d.msg == "Undefined label `'inner` for `break`"));
+    }
+
+    #[test]
+    fn calls_are_checked_against_the_arity() {
+        let add = "func add (a, b)\n    let c = a + b\n    c\n";
+        let arity = |diagnostic: &Diagnostic|diagnostic.msg.starts_with("Function `add` takes");
+
+        assert!(!diagnostics(&format!("{add}let x = add (1, 2)\n")).iter().any(arity));
+
+        // too few arguments is a partial application, because functions are curried
+        let (file, _) = convert(&format!("{add}let x = add 1\n"));
+        assert!(!file.diagnostics.iter().any(arity));
+        assert!(file.all_exprs().any(|(_, expr)|{
+            matches!(expr, Expr::Partial{args, ..} if args.len() == 1)
+        }));
+
+        let too_many = diagnostics(&format!("{add}let x = add (1, 2, 3)\n"));
+        assert!(too_many.iter().any(|d|{
+            d.msg == "Function `add` takes 2 argument(s), but 3 were given"
+        }));
+    }
+
+    #[test]
+    fn guarded_and_fallback_definitions_are_both_recorded() {
+        let (file, _) = convert("func f x if x > 1\n    x\nfunc f y\n    y\n");
+        assert!(!file.has_errors());
+
+        let guards = file.functions.iter()
+            .map(|function|function.guard.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(guards, [true, false]);
+
+        let root = file.get_scope(file.root_scope);
+        let definitions = root.functions.values()
+            .flat_map(|patterns|patterns.values())
+            .map(Vec::len)
+            .sum::<usize>();
+        assert_eq!(definitions, 2);
+    }
+
+    #[test]
+    fn sets_can_have_multiple_targets() {
+        let source = "let mut a = 1\nlet mut b = 2\nset a, b = (b, a)\n";
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors());
+
+        let targets = file.all_stmts()
+            .find_map(|(_, stmt)|match stmt {
+                Stmt::VarSetMulti{vars, ..}=>Some(vars.clone()),
+                _=>None,
+            })
+            .unwrap();
+        assert_eq!(targets, [var_named(&file, &interner, "a"), var_named(&file, &interner, "b")]);
+
+        let mismatch = diagnostics("let mut a = 1\nlet mut b = 2\nset a, b = (1, 2, 3)\n");
+        assert!(mismatch.iter().any(|d|d.msg == "Cannot assign 3 value(s) to 2 targets"));
+    }
+
+    #[test]
+    fn lets_destructure_tuples() {
+        let (file, interner) = convert("let (a, b) = (1, 2)\n");
+        assert!(!file.has_errors());
+
+        let vars = file.all_stmts()
+            .find_map(|(_, stmt)|match stmt {
+                Stmt::Destructure{vars, ..}=>Some(vars.clone()),
+                _=>None,
+            })
+            .unwrap();
+        assert_eq!(vars, [var_named(&file, &interner, "a"), var_named(&file, &interner, "b")]);
+
+        let mismatch = diagnostics("let (a, b) = 5\n");
+        assert!(mismatch.iter().any(|d|{
+            d.msg == "Cannot destructure 1 value(s) into a pattern of 2"
+        }));
+    }
+
+    #[test]
+    fn functions_can_be_used_before_their_definition() {
+        let even = "func even n\n    if n == 0\n        return 1\n    odd (n - 1)\n";
+        let odd = "func odd n\n    if n == 0\n        return 0\n    even (n - 1)\n";
+
+        for source in [format!("{even}{odd}"), format!("{odd}{even}")] {
+            let (file, _) = convert(&source);
+            assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+            let callees = file.all_exprs()
+                .filter_map(|(_, expr)|match expr {
+                    Expr::Operation{left, op: Operator::Apply, ..}=>Some(file.get_expr(*left)),
+                    _=>None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(callees.len(), 2);
+            assert!(callees.iter().all(|callee|matches!(callee, Expr::Function(_))));
+        }
+    }
+
+    #[test]
+    fn doc_comments_attach_to_functions() {
+        let source = concat!(
+            "/// Adds one.\n/// Never fails.\nfunc inc x\n    x + 1\n",
+            "func dec x\n    x - 1\n",
+        );
+        let (file, _) = convert(source);
+
+        let docs = file.functions.iter()
+            .map(|function|function.doc.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(docs, [Some("Adds one.\nNever fails."), None]);
+    }
+
+    #[test]
+    fn returns_belong_to_the_enclosing_function() {
+        let source = "func f x\n    if x > 1\n        return 1\n    x\n";
+        let (file, _) = convert(source);
+        assert!(!file.has_errors());
+
+        let returns = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::Return{function, ..}=>Some(*function),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+        // the one in the `if`, and the implicit one of the trailing `x`
+        assert_eq!(returns, [FunctionIndex(0), FunctionIndex(0)]);
+
+        let top_level = diagnostics("return 1\n");
+        assert!(top_level.iter().any(|d|d.msg == "`return` outside of a function"));
+    }
+
+    #[test]
+    fn mutable_borrows_need_a_mutable_var() {
+        assert!(File::parse("let mut x = 1\nlet r = &mut x\n").is_ok());
+
+        let notes = diagnostics("let mut x = 1\nlet t = typeof &mut x\n");
+        assert!(notes.iter().any(|d|d.msg == "The type of `&mut x` is `RefMut(Undetermined)`"));
+
+        let immutable = diagnostics("let x = 1\nlet r = &mut x\n");
+        assert!(immutable.iter().any(|d|{
+            d.msg == "Cannot mutably borrow `x` because it is not declared `mut`"
+        }));
+    }
+
+    #[test]
+    fn empty_function_bodies_return_unit() {
+        let (file, _) = convert("func f x\nlet y = 1\n");
+        assert!(!file.has_errors());
+
+        assert!(matches!(body(&file, 0).as_slice(), [Stmt::Return{data: None, ..}]));
+    }
+
+    #[test]
+    fn funcs_return_their_trailing_expression() {
+        let (file, _) = convert("func f x\n    x + 1\nproc p x\n    x + 1\n");
+        assert!(!file.has_errors());
+
+        assert!(matches!(body(&file, 0).as_slice(), [Stmt::Return{data: Some(_), ..}]));
+        assert!(matches!(body(&file, 1).as_slice(), [Stmt::Expr(_)]));
+    }
+
+    #[test]
+    fn underscore_lets_bind_nothing() {
+        let (file, interner) = convert("func f x\n    x + 1\nlet _ = f 1\n");
+        assert!(!file.has_errors());
+
+        // only the parameter
+        let vars = file.vars.iter()
+            .map(|var|interner.get_string(var.name))
+            .collect::<Vec<_>>();
+        assert_eq!(vars, ["x"]);
+
+        let calls = file.scope_stmts(file.root_scope)
+            .filter(|(_, stmt)|match stmt {
+                Stmt::Expr(expr)=>matches!(
+                    file.get_expr(*expr),
+                    Expr::Operation{op: Operator::Apply, ..},
+                ),
+                _=>false,
+            })
+            .count();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn undefined_names_point_at_the_name() {
+        let source = "let a = 1\nlet b = a + missing\n";
+        let undefined = diagnostics(source)
+            .into_iter()
+            .find(|d|d.msg == "Undefined name `missing`")
+            .unwrap();
+
+        assert_eq!(undefined.span, Some(22..29));
+        assert_eq!(&source[22..29], "missing");
+    }
+
+    #[test]
+    fn type_errors_point_at_the_leading_name() {
+        let spanned = |source: &str, msg: &str|{
+            let diagnostic = diagnostics(source).into_iter().find(|d|d.msg == msg).unwrap();
+            let span = diagnostic.span.expect("the diagnostic has no span");
+            source[span].to_string()
+        };
+
+        let source = "func f x\n    x\nlet a = f (1, 2)\n";
+        let msg = "Function `f` takes 1 argument(s), but 2 were given";
+        assert_eq!(spanned(source, msg), "f");
+
+        let source = "let flag = 1\nif flag + 1\n    print 1\n";
+        let msg = "The condition of an `if` must be a `Bool`, but `flag + 1` is a `Number`";
+        assert_eq!(spanned(source, msg), "flag");
+
+        let source = "func main ()\n    1\n";
+        let msg = "The entry point `main` must be a `proc`, since it has side effects";
+        assert_eq!(spanned(source, msg), "main");
+
+        let source = "let x = 1\nconst N = x + 1\n";
+        let msg = "The value of `const N` must be known at compile time, but `x + 1` is not";
+        assert_eq!(spanned(source, msg), "x");
+    }
+
+    #[test]
+    fn functions_without_a_pattern_take_nothing() {
+        let (file, _) = convert("func f\n    1\nfunc g ()\n    1\n");
+        assert!(!file.has_errors());
+
+        let [f, g] = &file.functions[..] else {
+            panic!("expected two functions, found {:?}", file.functions);
+        };
+        assert_eq!(f.pattern.arity(), 0);
+        assert_eq!(f.pattern, g.pattern);
+        assert!(f.params.is_empty() && g.params.is_empty());
+    }
+
+    #[test]
+    fn infix_functions_are_called_by_their_symbol() {
+        let (file, interner) = convert("infix func <+> (a, b)\n    a + b * 2\nlet x = 1 <+> 2\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let x = file.get_var(var_named(&file, &interner, "x"));
+        let init = file.get_expr(x.init.unwrap());
+        let Expr::Operation{left, right, op: Operator::Apply} = init else {
+            panic!("`1 <+> 2` should be a call");
+        };
+        assert!(matches!(file.get_expr(*left), Expr::Function(FunctionIndex(0))));
+        assert!(matches!(file.get_expr(*right), Expr::Group(args) if args.len() == 2));
+        assert_eq!(interner.get_string(file.functions[0].name), "<+>");
+    }
+
+    #[test]
+    fn returns_match_the_declared_type() {
+        let (file, _) = convert("func f x -> Number\n    1\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+        assert_eq!(file.functions[0].return_type, Some(Type::Number));
+
+        let wrong = diagnostics("func f x -> Number\n    \"one\"\n");
+        assert!(wrong.iter().any(|d|{
+            d.msg == "Function `f` is declared to return `Number`, but returns `String`"
+        }));
+    }
+
+    #[test]
+    fn named_outputs_index_the_returned_tuple() {
+        let f = "proc f (x) -> (sum: Number, diff: Number)\n    return (x + 1, x - 1)\n";
+        let source = format!("{f}let r = f 1\nlet d = r.diff\n");
+        let (file, interner) = convert(&source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let outputs = file.functions[0].outputs.iter()
+            .map(|name|interner.get_string(*name))
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, ["sum", "diff"]);
+        assert_eq!(
+            file.functions[0].return_type,
+            Some(Type::Tuple(vec![Type::Number, Type::Number])),
+        );
+
+        let d = file.get_var(var_named(&file, &interner, "d"));
+        assert!(matches!(file.get_expr(d.init.unwrap()), Expr::TupleIndex{index: 1, ..}));
+
+        let missing = diagnostics(&format!("{f}let r = f 1\nprint r.prod\n"));
+        assert!(missing.iter().any(|d|d.msg == "Function `f` has no output named `prod`"));
+    }
+
+    #[test]
+    fn none_is_not_unit() {
+        let source = "func f None\n    0\nfunc f x\n    1\nlet n = None\nlet u = ()\n";
+        let (file, interner) = convert(source);
+        // a `None` pattern and a name are different patterns, so these aren't duplicates
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let patterns = file.functions.iter()
+            .map(|function|&*function.pattern)
+            .collect::<Vec<_>>();
+        assert!(matches!(patterns.as_slice(), [Pattern::None, Pattern::Name(..)]));
+
+        let n = &file.get_var(var_named(&file, &interner, "n")).data_type;
+        let u = &file.get_var(var_named(&file, &interner, "u")).data_type;
+        assert_eq!(*n, Type::Option(Box::new(Type::Undetermined)));
+        assert_eq!(*u, Type::Tuple(Vec::new()));
+
+        let option = Type::Option(Box::new(Type::Number));
+        assert!(option.accepts(n));
+        assert!(option.accepts(&Type::Number));
+        assert!(!option.accepts(u));
+    }
+
+    #[test]
+    fn labeled_continues_restart_the_outer_scope() {
+        let source = concat!(
+            "let mut i = 0\n",
+            "scope 'outer\n",
+            "    set i = i + 1\n",
+            "    scope 'inner\n",
+            "        if i < 3\n",
+            "            continue 'outer\n",
+        );
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let outer = file.scopes.iter()
+            .find(|scope|scope.label.map(|label|interner.get_string(label)) == Some("outer"))
+            .unwrap();
+        let jumps = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::JumpTo(target)=>Some(*target),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(jumps, [outer.first]);
+        assert!(matches!(file.get_stmt(outer.first), Stmt::VarSet{..}));
+
+        let undefined = diagnostics("scope 'outer\n    continue 'nope\n");
+        assert!(undefined.iter().any(|d|d.msg == "Undefined label `'nope` for `continue`"));
+    }
+
+    #[test]
+    fn where_clauses_bind_for_their_expression() {
+        let (file, interner) = convert("func f a\n    result where result = a + 1\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let result = var_named(&file, &interner, "result");
+        let returned = body(&file, 0)
+            .into_iter()
+            .find_map(|stmt|match stmt {
+                Stmt::Return{data: Some(data), ..}=>Some(file.get_expr(*data)),
+                _=>None,
+            })
+            .unwrap();
+        assert!(matches!(returned, Expr::Var(var) if *var == result));
+    }
+
+    #[test]
+    fn resolved_expressions_print_by_name() {
+        let source = "func double x\n    x * 2\nlet a = 1\nlet b = double (a + 2) * a\n";
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let b = file.get_var(var_named(&file, &interner, "b"));
+        assert_eq!(file.expr_to_string(b.init.unwrap(), &interner), "double ((a + 2) * a)");
+    }
+
+    #[test]
+    fn only_functions_are_callable() {
+        let called = diagnostics("func f x\n    let y = x\n    y\nlet a = 1\nf a\n");
+        assert!(called.iter().all(|d|!d.is_error()), "{called:?}");
+
+        let applied = diagnostics("let a = 1\n2 a\n");
+        assert!(applied.iter().any(|d|d.msg == "A value of type `Number` is not callable"));
+    }
+
+    #[test]
+    fn main_must_be_a_proc() {
+        let (file, _) = convert("proc main ()\n    let x = 1\n    x\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+        assert_eq!(file.entry_point(), Some(FunctionIndex(0)));
+
+        let (file, _) = convert("func main ()\n    let x = 1\n    x\n");
+        assert_eq!(file.entry_point(), None);
+        assert!(file.diagnostics.iter().any(|d|{
+            d.msg == "The entry point `main` must be a `proc`, since it has side effects"
+        }));
+    }
+
+    #[test]
+    fn typeof_reports_the_operand_type() {
+        let (file, _) = convert("let t = typeof (1 + 2)\nlet u = typeof -3\n");
+        let notes = file.diagnostics.iter().map(|d|&*d.msg).collect::<Vec<_>>();
+
+        assert_eq!(notes, ["The type of `1 + 2` is `Number`", "The type of `-3` is `Number`"]);
+    }
+
+    #[test]
+    fn semicolons_keep_the_trailing_expression_from_being_returned() {
+        let (file, _) = convert("func f x\n    x + 1;\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let stmts = body(&file, 0);
+        assert!(matches!(stmts.as_slice(), [Stmt::Expr(_), Stmt::Return{data: None, ..}]));
+    }
+
+    #[test]
+    fn conditions_must_be_bools() {
+        let (file, _) = convert("let t = typeof (1 < 2)\nif 1 < 2\n    let x = 1\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+        assert!(file.diagnostics.iter().any(|d|d.msg == "The type of `1 < 2` is `Bool`"));
+
+        let (file, _) = convert("if 5\n    let x = 1\n");
+        assert!(file.diagnostics.iter().any(|d|{
+            d.msg == "The condition of an `if` must be a `Bool`, but `5` is a `Number`"
+        }));
+    }
+
+    #[test]
+    fn functions_are_values() {
+        let source = concat!(
+            "func f x\n    let y = x\n    y\n",
+            "func apply (h, x)\n    let y = h x\n    y\n",
+            "let g = f\n",
+            "let a = g 1\n",
+            "let b = apply (f, 1)\n",
+        );
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+        let is_f = |expr: ExprIndex|{
+            matches!(file.get_expr(expr), Expr::Function(function) if *function == FunctionIndex(0))
+        };
+
+        let g = file.get_var(var_named(&file, &interner, "g"));
+        assert!(is_f(g.init.unwrap()));
+        let a = file.get_var(var_named(&file, &interner, "a")).init.unwrap();
+        assert!(is_f(callee(&file, a)));
+
+        let b = file.get_var(var_named(&file, &interner, "b")).init.unwrap();
+        let Expr::Operation{right: args, ..} = file.get_expr(b) else {
+            panic!("`b` should be a call");
+        };
+        let Expr::Group(args) = file.get_expr(*args) else {
+            panic!("the arguments should be a tuple");
+        };
+        assert!(is_f(args[0]));
+    }
+
+    #[test]
+    fn partial_applications_become_calls_when_applied() {
+        let add = "func add (a, b)\n    let c = a + b\n    c\n";
+        let source = format!("{add}let inc = add 1\nlet x = (add 1) 2\n");
+        let (file, interner) = convert(&source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let inc = file.get_var(var_named(&file, &interner, "inc")).init.unwrap();
+        assert_eq!(file.expr_to_string(inc, &interner), "add 1");
+        let x = file.get_var(var_named(&file, &interner, "x")).init.unwrap();
+        assert_eq!(file.expr_to_string(x, &interner), "add (1, 2)");
+
+        // with both arguments it is inlined like any other call
+        let inlined = "func add (a, b)\n    a + b\nlet x = (add 1) 2\n";
+        let (file, interner) = File::parse(inlined).unwrap();
+        let x = file.get_var(var_named(&file, &interner, "x")).init.unwrap();
+        assert_eq!(file.expr_to_string(x, &interner), "1 + 2");
+    }
+
+    #[test]
+    fn consts_must_be_constant() {
+        use crate::mid_ast::folding::Constant;
+
+        let (file, interner) = convert("const N = 2 * 3\nlet x = N\n");
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+        let n = interner.get_index("N").unwrap();
+        assert_eq!(file.constants.get(&n), Some(&Constant::Number(6)));
+
+        let (file, _) = convert("let x = 1\nconst N = x + 1\n");
+        assert!(file.diagnostics.iter().any(|d|{
+            d.msg == "The value of `const N` must be known at compile time, but `x + 1` is not"
+        }));
+    }
+
+    #[test]
+    fn scopes_give_the_value_they_break_with() {
+        let source = "let x = scope 'out\n    if 1 < 2\n        break 'out 1\n    2\n";
+        let (file, interner) = convert(source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let x = var_named(&file, &interner, "x");
+        assert_eq!(file.get_var(x).data_type, Type::Number);
+        let values = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::VarSet{var, data, ..} if *var == x=>Some(file.get_expr(*data)),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(values.as_slice(), [Expr::Number(1), Expr::Number(2)]));
+
+        let source = "let x = scope 'out\n    if 1 < 2\n        break 'out 1\n    \"two\"\n";
+        let (file, _) = convert(source);
+        assert!(file.diagnostics.iter().any(|d|{
+            d.msg == "The `break`s out of `'out` give different types: `Number` and `String`"
+        }));
+    }
+
+    #[test]
+    fn duplicate_definitions_name_both_locations() {
+        let source = "func f x\n    x\nfunc f y\n    y\n";
+        let duplicates = diagnostics(source)
+            .into_iter()
+            .filter(|d|d.msg.starts_with("Duplicate definition"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates[0].msg,
+            "Duplicate definition of `f`: it has the same pattern as the one on line 1",
+        );
+        assert_eq!(duplicates[0].span, Some(20..21));
+
+        // guarded definitions are alternatives, not duplicates
+        let guarded = "func f x if x > 1\n    x\nfunc f y\n    y\n";
+        assert!(!diagnostics(guarded).iter().any(|d|d.msg.starts_with("Duplicate definition")));
+    }
+
+    #[test]
+    fn numeric_casts_are_legal() {
+        assert!(File::parse("let x = 1 as Number\n").is_ok());
+
+        let illegal = diagnostics("let x = 1 as String\n");
+        assert!(illegal.iter().any(|d|d.msg == "Cannot cast `Number` to `String`"));
+    }
+
+    #[test]
+    fn cfg_gated_statements_follow_the_features() {
+        let source = "#[cfg(debug)]\nlet x = 1\nlet y = 2\n";
+        let names = |features: &[&str]|{
+            let (file, interner) = File::parse_with_features(source, features).unwrap();
+            file.vars.iter()
+                .map(|var|interner.get_string(var.name).to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&[]), ["y"]);
+        assert_eq!(names(&["debug"]), ["x", "y"]);
+    }
+
+    #[test]
+    fn list_elements_have_one_type() {
+        let (file, _) = File::parse("let xs: [Number] = [1, 2, 3]\n").unwrap();
+        assert_eq!(file.vars[0].data_type, Type::List(Box::new(Type::Number)));
+
+        let mixed = diagnostics("let xs = [1, \"a\"]\n");
+        assert!(mixed.iter().any(|d|{
+            d.msg == "List elements have different types: `Number` and `String`"
+        }));
+
+        let source = "let xs: [String] = [1]\n";
+        let wrong = diagnostics(source)
+            .into_iter()
+            .find(|d|d.msg.starts_with("`xs` is declared as"))
+            .unwrap();
+        assert_eq!(
+            wrong.msg,
+            "`xs` is declared as `List(String)`, but its value is `List(Number)`",
+        );
+        assert_eq!(wrong.span, Some(4..6));
+    }
+
+    #[test]
+    fn endless_loops_in_funcs_are_warned_about() {
+        let source = concat!(
+            "func spin n\n    loop\n        let a = n\n",
+            "func count n\n    loop\n        break\n    n\n",
+        );
+        let endless = diagnostics(source)
+            .into_iter()
+            .filter(|d|d.msg.contains("never returns"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(endless.len(), 1);
+        assert_eq!(
+            endless[0].msg,
+            "`func spin` never returns, because nothing leaves the `loop` on line 2",
+        );
+        assert_eq!(endless[0].span, Some(5..9));
+    }
+
+    #[test]
+    fn defaults_can_name_consts() {
+        let source = "const LIMIT = 10\nfunc f (x, y = LIMIT)\n    x + y\nlet z = f 1\n";
+        let (file, _) = File::parse(source).unwrap();
+
+        assert!(matches!(file.functions[0].defaults.as_slice(), [Expr::Number(10)]));
+
+        // two statements, so the calls aren't inlined away
+        let func = "func f (x, y = LIMIT)\n    let z = x + y\n    z\n";
+        let source = format!("const LIMIT = 10\n{func}let a = f 1\nlet b = f (1, 2)\n");
+        let (file, interner) = convert(&source);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let call = |name: &str|{
+            let init = file.get_var(var_named(&file, &interner, name)).init.unwrap();
+            file.expr_to_string(init, &interner)
+        };
+        assert_eq!(call("a"), "f (1, 10)");
+        assert_eq!(call("b"), "f (1, 2)");
+    }
+
+    #[test]
+    fn type_assertions() {
+        let (file, _) = convert("let x = 1 + 2\nassert_type x : Number\n");
+        assert!(file.diagnostics.is_empty(), "{:?}", file.diagnostics);
+
+        let (file, _) = convert("let x = 1 + 2\nassert_type x : String\n");
+        assert!(file.diagnostics.iter().any(|d|{
+            d.msg == "`assert_type` failed: `x` is a `Number`, not a `String`"
+        }));
+    }
 }
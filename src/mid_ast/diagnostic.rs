@@ -0,0 +1,206 @@
+// diagnostics emitted by the conversion and analysis passes
+
+
+use parser_helper::SimpleError;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    ops::Range,
+};
+
+
+/// How diagnostics are printed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Rendered for people, with the source when there is a span.
+    Human,
+    /// A JSON array of objects with `file`, `severity`, `message`, and `span` fields.
+    Json,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub msg: Cow<'static, str>,
+    /// Byte offsets into the source, if the diagnostic can point at something.
+    pub span: Option<Range<usize>>,
+}
+impl Diagnostic {
+    pub fn error<S: Into<Cow<'static, str>>>(msg: S)->Self {
+        Diagnostic {
+            severity: Severity::Error,
+            msg: msg.into(),
+            span: None,
+        }
+    }
+
+    pub fn warning<S: Into<Cow<'static, str>>>(msg: S)->Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            msg: msg.into(),
+            span: None,
+        }
+    }
+
+    pub fn note<S: Into<Cow<'static, str>>>(msg: S)->Self {
+        Diagnostic {
+            severity: Severity::Note,
+            msg: msg.into(),
+            span: None,
+        }
+    }
+
+    /// An error caused by a bug in the compiler instead of the program being compiled.
+    pub fn internal<S: Into<Cow<'static, str>>>(msg: S)->Self {
+        Self::error(format!("Internal compiler error: {}", msg.into()))
+    }
+
+    pub fn with_span(mut self, span: Range<usize>)->Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Adds the span if there is one, for diagnostics that can't always point at the source.
+    pub fn with_span_if(mut self, span: Option<Range<usize>>)->Self {
+        self.span = span.or(self.span);
+        self
+    }
+
+    #[inline]
+    pub fn is_error(&self)->bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn eprint(&self, filename: &str) {
+        match self.severity {
+            Severity::Error=>eprintln!("Error in `{filename}`: {}", self.msg),
+            Severity::Warning=>eprintln!("Warning in `{filename}`: {}", self.msg),
+            Severity::Note=>eprintln!("Note in `{filename}`: {}", self.msg),
+        }
+    }
+
+    /// Prints the diagnostic, with the source it points at if it has a span.
+    pub fn eprint_with_source(&self, source: &str, filename: &str) {
+        match &self.span {
+            Some(span)=>{
+                let error = SimpleError {
+                    msg: self.msg.clone(),
+                    span: span.clone(),
+                };
+                // the source rendering is for errors, so mark anything else
+                match self.severity {
+                    Severity::Error=>{},
+                    Severity::Warning=>eprint!("Warning: "),
+                    Severity::Note=>eprint!("Note: "),
+                }
+                error.eprint_with_source(source, filename);
+            },
+            None=>self.eprint(filename),
+        }
+    }
+
+    fn to_json<'a>(&'a self, filename: &'a str)->JsonDiagnostic<'a> {
+        JsonDiagnostic {
+            file: filename,
+            severity: self.severity,
+            message: &self.msg,
+            span: self.span.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    file: &'a str,
+    severity: Severity,
+    message: &'a str,
+    /// Byte offsets into the source.
+    span: Option<Range<usize>>,
+}
+
+/// Renders the diagnostics as a JSON array.
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic], filename: &str)->String {
+    let json = diagnostics
+        .iter()
+        .map(|diagnostic|diagnostic.to_json(filename))
+        .collect::<Vec<_>>();
+
+    return serde_json::to_string(&json).unwrap();
+}
+
+/// Errors from running the whole pipeline on a program.
+#[derive(Debug)]
+pub enum ProgramError {
+    /// Every parse error, in the order they were found. There is always at least one.
+    Parse(Vec<SimpleError<Cow<'static, str>>>),
+    /// All of the diagnostics from conversion and analysis. At least one is an error.
+    Diagnostics(Vec<Diagnostic>),
+}
+impl ProgramError {
+    pub fn eprint_with_source(&self, source: &str, filename: &str) {
+        match self {
+            Self::Parse(errors)=>for error in errors {
+                error.eprint_with_source(source, filename);
+            },
+            Self::Diagnostics(diagnostics)=>for diagnostic in diagnostics {
+                diagnostic.eprint_with_source(source, filename);
+            },
+        }
+    }
+
+    /// Renders the error as a JSON array of diagnostics, with one for each parse error.
+    pub fn to_json(&self, filename: &str)->String {
+        match self {
+            Self::Parse(errors)=>{
+                let json = errors.iter()
+                    .map(|e|JsonDiagnostic {
+                        file: filename,
+                        severity: Severity::Error,
+                        message: &e.msg,
+                        span: Some(e.span.clone()),
+                    })
+                    .collect::<Vec<_>>();
+
+                serde_json::to_string(&json).unwrap()
+            },
+            Self::Diagnostics(diagnostics)=>diagnostics_to_json(diagnostics, filename),
+        }
+    }
+
+    /// Prints the error to stderr in the given format.
+    pub fn eprint(&self, format: ErrorFormat, source: &str, filename: &str) {
+        match format {
+            ErrorFormat::Human=>self.eprint_with_source(source, filename),
+            ErrorFormat::Json=>eprintln!("{}", self.to_json(filename)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::mid_ast::tree::File;
+
+    #[test]
+    fn parse_errors_render_as_json() {
+        let Err(error) = File::parse("let = 1\n") else {
+            panic!("a `let` without a name should not parse");
+        };
+
+        assert_eq!(
+            error.to_json("main.gc"),
+            concat!(
+                r#"[{"file":"main.gc","severity":"error","#,
+                r#""message":"Unexpected token in pattern","span":{"start":4,"end":5}}]"#,
+            ),
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// side effect analysis for reordering
+
+
+use super::tree::*;
+
+
+/// Finds every statement that could call a `proc`. These are barriers that the optimizer must not
+/// reorder other statements across. Statements that only call `func`s can be reordered freely.
+///
+/// The arguments of a call are barriers within the call the same way, so the ones that could call
+/// a `proc` are recorded in `File::sequenced` to keep their source order.
+pub fn mark_barriers(file: &mut File) {
+    let barriers = file.all_stmts()
+        .filter(|(_, stmt)|is_barrier(file, stmt))
+        .map(|(index, _)|index)
+        .collect();
+
+    let sequenced = file.all_exprs()
+        .filter_map(|(_, expr)|match expr {
+            Expr::Operation{right, op: Operator::Apply, ..}=>match file.get_expr(*right) {
+                Expr::Group(items)=>Some((*right, items)),
+                _=>None,
+            },
+            _=>None,
+        })
+        .map(|(group, items)|{
+            let procs = items.iter()
+                .copied()
+                .filter(|item|calls_proc(file, *item))
+                .collect::<Vec<_>>();
+            (group, procs)
+        })
+        .filter(|(_, procs)|procs.len() > 1)
+        .collect();
+
+    file.barriers = barriers;
+    file.sequenced = sequenced;
+}
+
+/// Checks if the statement could call a `proc` or changes control flow. Nested blocks are their own
+/// statements, so `if` and `cond` are always barriers to keep their blocks in place.
+pub fn is_barrier(file: &File, stmt: &Stmt)->bool {
+    match stmt {
+        Stmt::VarDef(var)=>match file.get_var(*var).init {
+            Some(init)=>calls_proc(file, init),
+            None=>false,
+        },
+        Stmt::Destructure{data, ..}|
+            Stmt::VarSet{data, ..}|
+            Stmt::VarSetMulti{data, ..}|
+            Stmt::Disown(data)|
+            Stmt::Expr(data)=>calls_proc(file, *data),
+        Stmt::Return{..}|
+            Stmt::JumpTo(_)|
+            Stmt::IfElse{..}|
+            Stmt::Conditional{..}=>true,
+        Stmt::Skip=>false,
+    }
+}
+
+/// Checks if evaluating the expression could call a `proc`. Anything called that isn't known to be
+/// a `func` is assumed to be a `proc`, including builtins, overloaded names, and variables.
+pub fn calls_proc(file: &File, expr: ExprIndex)->bool {
+    match file.get_expr(expr) {
+        Expr::Operation{left, right, op: Operator::Apply}=>{
+            let is_func = match file.get_expr(callee(file, *left)) {
+                Expr::Function(function)|
+                    Expr::Partial{function, ..}=>!file.get_function(*function).is_proc,
+                _=>false,
+            };
+
+            !is_func || calls_proc(file, *left) || calls_proc(file, *right)
+        },
+        Expr::Operation{left, right, ..}=>calls_proc(file, *left) || calls_proc(file, *right),
+        Expr::Group(items)|
+            Expr::List(items)|
+            Expr::Partial{args: items, ..}=>items.iter().any(|item|calls_proc(file, *item)),
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Borrow(inner)|
+            Expr::BorrowMut(inner)|
+            Expr::Deref(inner)|
+            Expr::Negate(inner)|
+            Expr::Heap(inner)|
+            Expr::Stack(inner)=>calls_proc(file, *inner),
+        Expr::RawVar(_)|
+            Expr::Number(_)|
+            Expr::String(_)|
+            Expr::Char(_)|
+            Expr::None|
+            Expr::TypeOf(_)|
+            Expr::Var(_)|
+            Expr::Function(_)|
+            Expr::Skip=>false,
+    }
+}
+
+/// The function being applied in a chain of applications like `f a b`. Functions are values, so
+/// an immutable variable bound to one, like `g` after `let g = f`, is followed to the function.
+pub fn callee(file: &File, mut expr: ExprIndex)->ExprIndex {
+    while let Expr::Operation{left, op: Operator::Apply, ..} = file.get_expr(expr) {
+        expr = *left;
+    }
+
+    while let Expr::Var(var) = file.get_expr(expr) {
+        let var = file.get_var(*var);
+        let init = match var.init {
+            Some(init) if !var.mutable=>init,
+            _=>break,
+        };
+        match file.get_expr(init) {
+            Expr::Function(_)|Expr::Var(_)=>expr = init,
+            _=>break,
+        }
+    }
+
+    return expr;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two statements, so calls to it aren't inlined away.
+    const FUNC: &str = "func f x\n    let y = x + 1\n    y\n";
+    const PROC: &str = "proc p x\n    print x\n";
+
+    #[test]
+    fn proc_calls_are_barriers() {
+        let source = format!("{FUNC}{PROC}let a = f 1\np 2\nlet b = f 3\n");
+        let (file, _) = File::parse(&source).unwrap();
+
+        let barriers = file.scope_stmts(file.root_scope)
+            .filter(|(_, stmt)|!matches!(stmt, Stmt::Skip))
+            .map(|(index, stmt)|(stmt, file.barriers.contains(&index)))
+            .collect::<Vec<_>>();
+        // only the `p 2`, and not the `let`s of the `f` calls around it
+        assert_eq!(barriers.iter().filter(|(_, barrier)|*barrier).count(), 1);
+        assert!(barriers.len() >= 3);
+        for (stmt, barrier) in barriers {
+            assert_eq!(barrier, matches!(stmt, Stmt::Expr(_)), "{stmt:?}");
+        }
+    }
+
+    #[test]
+    fn proc_calling_arguments_keep_their_order() {
+        let q = "proc q (a, b, c)\n    print a\n    print b\n";
+        let source = format!("{FUNC}{PROC}{q}q (p 1, f 2, p 3)\nq (f 1, p 2, f 3)\n");
+        let (file, interner) = File::parse(&source).unwrap();
+
+        // the second call only has one argument that calls a `proc`, so it has no order to keep
+        assert_eq!(file.sequenced.len(), 1);
+        let order = file.sequenced.values()
+            .next()
+            .unwrap()
+            .iter()
+            .map(|arg|file.expr_to_string(*arg, &interner))
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["p 1", "p 3"]);
+    }
+}
@@ -0,0 +1,147 @@
+// escape analysis for deciding what gets hoisted to the heap
+
+
+use crate::StringInterner;
+use super::tree::*;
+
+
+/// Hoists every variable that has a borrow of it outlive it to the heap. A borrow outlives the
+/// variable if it is returned, or assigned to a variable from an enclosing scope. Variables that
+/// already have a location, like ones declared with `heap`, are left alone, and variables pinned
+/// with `stack` are errors instead of being hoisted. Needs the variable uses to be recorded first.
+pub fn hoist_escaping(file: &mut File, interner: &StringInterner) {
+    let mut diagnostics = Vec::new();
+
+    for index in 0..file.vars.len() {
+        let var = VarIndex(index);
+        let var_ref = file.get_var(var);
+        if !matches!(var_ref.mem_loc, MemoryLocation::Undetermined) {
+            continue;
+        }
+
+        let escape = var_ref.borrows
+            .iter()
+            .chain(var_ref.mut_borrows.iter())
+            .copied()
+            .find(|site|escapes_at(file, var, *site));
+
+        match (escape, var_ref.stack_pinned) {
+            (Some(site), true)=>{
+                let mut msg = format!(
+                    "`{}` is pinned to the stack, but a borrow of it escapes",
+                    interner.get_string(var_ref.name),
+                );
+                if let Some(context) = file.scope_context(var_ref.in_scope, interner) {
+                    msg = format!("{msg} ({context})");
+                }
+                diagnostics.push(match file.site_span(var, site) {
+                    Some(span)=>Diagnostic::error(msg).with_span(span),
+                    None=>Diagnostic::error(msg),
+                });
+            },
+            (Some(_), false)=>file.get_mut_var(var).mem_loc = MemoryLocation::Heap,
+            (None, _)=>{},
+        }
+    }
+
+    file.diagnostics.append(&mut diagnostics);
+}
+
+/// Checks if the statement at `site` makes a borrow of the variable outlive it.
+fn escapes_at(file: &File, var: VarIndex, site: StmtIndex)->bool {
+    let scope = file.get_var(var).in_scope;
+
+    match file.get_stmt(site) {
+        Stmt::Return{data: Some(data), ..}=>borrows(file, *data, var),
+        // undefined targets were already reported during conversion
+        Stmt::VarSet{var: holder, ..} if *holder == VarIndex::invalid()=>false,
+        Stmt::VarSet{var: holder, data, ..}=>{
+            let holder_scope = file.get_var(*holder).in_scope;
+            file.is_ancestor(holder_scope, scope) && borrows(file, *data, var)
+        },
+        Stmt::VarSetMulti{vars, data, ..}=>{
+            let outer = vars.iter()
+                .filter(|holder|**holder != VarIndex::invalid())
+                .map(|holder|file.get_var(*holder).in_scope)
+                .any(|holder_scope|file.is_ancestor(holder_scope, scope));
+            outer && borrows(file, *data, var)
+        },
+        _=>false,
+    }
+}
+
+/// Checks if the expression is a borrow of the variable, or a tuple or list with one in it.
+fn borrows(file: &File, expr: ExprIndex, var: VarIndex)->bool {
+    match file.get_expr(expr) {
+        Expr::Borrow(inner)|Expr::BorrowMut(inner)=>{
+            matches!(file.get_expr(*inner), Expr::Var(borrowed) if *borrowed == var)
+        },
+        Expr::Group(items)|Expr::List(items)=>items.iter().any(|item|borrows(file, *item, var)),
+        _=>false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_data_stays_on_the_heap() {
+        let source = concat!(
+            "func f x\n",
+            "    let h = heap 5\n",
+            "    let s = 5 + x\n",
+            "    let y = x\n",
+            "    return &y\n",
+        );
+        let (file, interner) = File::parse(source).unwrap();
+        let mem_loc = |name: &str|{
+            &file.vars.iter()
+                .find(|var|interner.get_string(var.name) == name)
+                .unwrap()
+                .mem_loc
+        };
+
+        // nothing borrows `h`, but it was declared on the heap
+        assert!(matches!(mem_loc("h"), MemoryLocation::Heap));
+        assert!(matches!(mem_loc("s"), MemoryLocation::Undetermined));
+        // a borrow of `y` escapes, so it is hoisted
+        assert!(matches!(mem_loc("y"), MemoryLocation::Heap));
+    }
+
+    #[test]
+    fn escaping_borrows_of_constants_are_hoisted() {
+        let (file, interner) = File::parse("func f\n    let y = 5\n    return &y\n").unwrap();
+        let y = file.vars.iter()
+            .find(|var|interner.get_string(var.name) == "y")
+            .unwrap();
+
+        assert!(y.constant.is_none());
+        assert!(matches!(y.mem_loc, MemoryLocation::Heap));
+    }
+
+    #[test]
+    fn stack_pins_that_escape_are_errors() {
+        let legal = "func f x\n    let y = stack x\n    let r = &y\n    r\n";
+        assert!(File::parse(legal).is_ok());
+
+        let source = "func f x\n    let y = stack x\n    return &y\n";
+        let Err(ProgramError::Diagnostics(diagnostics)) = File::parse(source) else {
+            panic!("returning a borrow of a stack pinned variable should be an error");
+        };
+        let pinned = diagnostics.iter()
+            .find(|d|d.msg.starts_with("`y` is pinned to the stack"))
+            .unwrap();
+        assert_eq!(pinned.span, Some(source.rfind('y').unwrap()..source.len() - 1));
+    }
+
+    #[test]
+    fn undefined_holders_are_skipped() {
+        let source = "let a = 1\nscope\n    let b = 2\n    set missing = &b\n";
+        let Err(ProgramError::Diagnostics(diagnostics)) = File::parse(source) else {
+            panic!("setting an undefined variable should be an error");
+        };
+        assert!(diagnostics.iter().any(|d|d.msg == "Undefined variable `missing`"));
+    }
+}
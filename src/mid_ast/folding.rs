@@ -0,0 +1,134 @@
+// constant folding
+
+
+use crate::Index;
+use super::tree::*;
+
+
+/// A value known at compile time.
+#[derive(Debug, Copy, Clone, PartialEq, Hash)]
+pub enum Constant {
+    Number(i64),
+    Bool(bool),
+    String(Index),
+    Char(char),
+    None,
+}
+impl Constant {
+    /// The literal for the constant. There are no `Bool` literals, so they have none.
+    pub fn to_expr(&self)->Option<Expr> {
+        match self {
+            Constant::Number(n)=>Some(Expr::Number(*n)),
+            Constant::String(s)=>Some(Expr::String(*s)),
+            Constant::Char(c)=>Some(Expr::Char(*c)),
+            Constant::None=>Some(Expr::None),
+            Constant::Bool(_)=>Option::None,
+        }
+    }
+}
+
+/// Evaluates the expression if it only depends on constants. Operations that would fail at
+/// runtime (overflow, division by zero) are not folded.
+pub fn fold(file: &File, expr: ExprIndex)->Option<Constant> {
+    use Constant::*;
+
+    match file.get_expr(expr) {
+        Expr::Number(n)=>Some(Number(*n)),
+        Expr::String(s)=>Some(String(*s)),
+        Expr::Char(c)=>Some(Char(*c)),
+        Expr::None=>Some(None),
+        Expr::Var(var)=>file.get_var(*var).constant,
+        Expr::Negate(inner)=>match fold(file, *inner)? {
+            Number(n)=>Some(Number(n.checked_neg()?)),
+            _=>Option::None,
+        },
+        Expr::Operation{left, right, op}=>{
+            let left = fold(file, *left)?;
+
+            // short circuit before looking at the right side
+            match (op, left) {
+                (Operator::LogicAnd, Bool(false))=>return Some(Bool(false)),
+                (Operator::LogicOr, Bool(true))=>return Some(Bool(true)),
+                _=>{},
+            }
+
+            let right = fold(file, *right)?;
+
+            fold_operation(op, left, right)
+        },
+        _=>Option::None,
+    }
+}
+
+/// Marks variables that are initialized with a constant and never changed as compile-time
+/// constants. They are inlined instead of being given memory. Borrowed variables need memory to
+/// point at, so they are never promoted. Needs the variable uses to be recorded first.
+pub fn promote_constants(file: &mut File) {
+    for index in 0..file.vars.len() {
+        let var = &file.vars[index];
+        if var.assigns.len() > 0 || var.mut_borrows.len() > 0 || var.borrows.len() > 0 {
+            continue;
+        }
+
+        let constant = match var.init {
+            Some(init)=>fold(file, init),
+            Option::None=>continue,
+        };
+
+        if let Some(constant) = constant {
+            let var = &mut file.vars[index];
+            var.constant = Some(constant);
+            var.mem_loc = MemoryLocation::Inlined;
+        }
+    }
+}
+
+fn fold_operation(op: &Operator, left: Constant, right: Constant)->Option<Constant> {
+    use Constant::*;
+
+    Some(match (op, left, right) {
+        (Operator::Add, Number(l), Number(r))=>Number(l.checked_add(r)?),
+        (Operator::Sub, Number(l), Number(r))=>Number(l.checked_sub(r)?),
+        (Operator::Mul, Number(l), Number(r))=>Number(l.checked_mul(r)?),
+        (Operator::Div, Number(l), Number(r))=>Number(l.checked_div(r)?),
+        (Operator::Rem, Number(l), Number(r))=>Number(l.checked_rem(r)?),
+
+        (Operator::And, Number(l), Number(r))=>Number(l & r),
+        (Operator::Or, Number(l), Number(r))=>Number(l | r),
+        (Operator::Xor, Number(l), Number(r))=>Number(l ^ r),
+
+        (Operator::Equal, l, r)=>Bool(l == r),
+        (Operator::NotEqual, l, r)=>Bool(l != r),
+        (Operator::Less, Number(l), Number(r))=>Bool(l < r),
+        (Operator::LessEqual, Number(l), Number(r))=>Bool(l <= r),
+        (Operator::Greater, Number(l), Number(r))=>Bool(l > r),
+        (Operator::GreaterEqual, Number(l), Number(r))=>Bool(l >= r),
+
+        (Operator::LogicAnd, Bool(l), Bool(r))=>Bool(l && r),
+        (Operator::LogicOr, Bool(l), Bool(r))=>Bool(l || r),
+
+        _=>return Option::None,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_constant_lets_are_inlined() {
+        let (file, interner) = File::parse("let a = 1 + 2\nlet mut b = 1\nset b = a\n").unwrap();
+        let var = |name: &str|{
+            file.vars.iter()
+                .find(|var|interner.get_string(var.name) == name)
+                .unwrap()
+        };
+
+        assert_eq!(var("a").constant, Some(Constant::Number(3)));
+        assert!(matches!(var("a").mem_loc, MemoryLocation::Inlined));
+
+        assert_eq!(var("b").constant, Option::None);
+        assert!(!matches!(var("b").mem_loc, MemoryLocation::Inlined));
+    }
+}
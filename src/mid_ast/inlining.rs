@@ -0,0 +1,319 @@
+// inlining of small `func`s
+
+
+use std::mem;
+use fnv::{
+    FnvHashMap,
+    FnvHashSet,
+};
+use super::{
+    tree::*,
+    effects::calls_proc,
+};
+
+
+/// The most expressions a `func`'s body can have and still be inlined.
+const MAX_INLINE_SIZE: usize = 8;
+
+
+/// Replaces calls to small `func`s with their bodies. A `func` is small if its whole body is a
+/// single returned expression of at most `MAX_INLINE_SIZE` expressions that only uses its
+/// parameters. Every name in the body is already resolved to one of the parameters or to a
+/// function, so nothing at the call site can capture it.
+///
+/// The arguments replace the parameters, so a call is only inlined if none of the arguments
+/// could call a `proc`, and arguments that are used more than once are a variable or a literal.
+/// Recursive `func`s are never inlined, including ones that call each other in a cycle, and the
+/// inlined bodies are not inlined into again.
+pub fn inline_functions(file: &mut File) {
+    let mut candidates = (0..file.functions.len())
+        .map(FunctionIndex)
+        .filter_map(|index|Some((index, candidate(file, index)?)))
+        .collect::<FnvHashMap<_, _>>();
+
+    let call_graph = candidates.iter()
+        .map(|(index, body)|{
+            let mut called = Vec::new();
+            called_functions(file, *body, &mut called);
+            (*index, called)
+        })
+        .collect::<FnvHashMap<_, _>>();
+    candidates.retain(|index, _|!reaches(&call_graph, *index, *index));
+
+    let calls = file.all_exprs()
+        .map(|(index, _)|index)
+        .collect::<Vec<_>>();
+
+    for call in calls {
+        // a call that was moved as an argument of an inlined call is looked up again here
+        let (left, right) = match file.get_expr(call) {
+            Expr::Operation{left, right, op: Operator::Apply}=>(*left, *right),
+            _=>continue,
+        };
+        let (function, body) = match file.get_expr(left) {
+            Expr::Function(function)=>match candidates.get(function) {
+                Some(body)=>(*function, *body),
+                None=>continue,
+            },
+            _=>continue,
+        };
+
+        let args = match file.get_expr(right) {
+            Expr::Group(items)=>items.clone(),
+            _=>vec![right],
+        };
+        let params = &file.get_function(function).params;
+        if args.len() != params.len() || args.iter().any(|arg|calls_proc(file, *arg)) {
+            continue;
+        }
+
+        let mut uses = FnvHashMap::default();
+        count_uses(file, body, &mut uses);
+        let substitutions = params.iter()
+            .copied()
+            .zip(args.iter().copied())
+            .collect::<FnvHashMap<_, _>>();
+        let duplicates_complex = substitutions.iter()
+            .filter(|(param, _)|uses.get(param).copied().unwrap_or(0) > 1)
+            .any(|(_, arg)|!is_trivial(file, *arg));
+        if duplicates_complex {
+            continue;
+        }
+
+        let inlined = copy_expr(file, body, &substitutions, &uses, call);
+        *file.get_mut_expr(call) = inlined;
+
+        // the pieces of the call aren't part of the program anymore
+        *file.get_mut_expr(left) = Expr::Skip;
+        if !args.contains(&right) {
+            *file.get_mut_expr(right) = Expr::Skip;
+        }
+    }
+}
+
+/// Returns the returned expression of the `func` if it can be inlined.
+fn candidate(file: &File, index: FunctionIndex)->Option<ExprIndex> {
+    let function = file.get_function(index);
+    if function.is_proc || function.guard.is_some() {
+        return None;
+    }
+    // nested tuples and discarded parameters don't map one argument to one parameter
+    if function.pattern.arity() != function.params.len() {
+        return None;
+    }
+
+    let block = &function.block;
+    if block.first != block.last || file.stmt_with_patches(block.first.root).count() != 1 {
+        return None;
+    }
+    let body = match file.get_stmt(block.first) {
+        Stmt::Return{data: Some(data), ..}=>*data,
+        _=>return None,
+    };
+
+    let size = inline_size(file, body, index, &function.params)?;
+    if size > MAX_INLINE_SIZE {
+        return None;
+    }
+
+    return Some(body);
+}
+
+/// The number of expressions in the body, or `None` if something in it keeps it from being
+/// inlined. Borrows and memory placement depend on where the parameters live, so they are not
+/// inlined.
+fn inline_size(
+    file: &File,
+    expr: ExprIndex,
+    function: FunctionIndex,
+    params: &[VarIndex],
+)->Option<usize> {
+    let size = |expr|inline_size(file, expr, function, params);
+
+    Some(1 + match file.get_expr(expr) {
+        Expr::Operation{left, right, ..}=>size(*left)? + size(*right)?,
+        Expr::Group(items)=>{
+            let mut total = 0;
+            for item in items {
+                total += size(*item)?;
+            }
+            total
+        },
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Negate(inner)=>size(*inner)?,
+        Expr::Var(var) if params.contains(var)=>0,
+        Expr::Function(callee) if *callee != function=>0,
+        Expr::Number(_)|
+            Expr::String(_)|
+            Expr::Char(_)|
+            Expr::None=>0,
+        _=>return None,
+    })
+}
+
+/// Counts how many times each variable is used in the expression.
+fn count_uses(file: &File, expr: ExprIndex, uses: &mut FnvHashMap<VarIndex, usize>) {
+    match file.get_expr(expr) {
+        Expr::Var(var)=>*uses.entry(*var).or_default() += 1,
+        Expr::Operation{left, right, ..}=>{
+            count_uses(file, *left, uses);
+            count_uses(file, *right, uses);
+        },
+        Expr::Group(items)=>for item in items {
+            count_uses(file, *item, uses);
+        },
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Negate(inner)=>count_uses(file, *inner, uses),
+        _=>{},
+    }
+}
+
+/// Collects every function the expression refers to.
+fn called_functions(file: &File, expr: ExprIndex, called: &mut Vec<FunctionIndex>) {
+    match file.get_expr(expr) {
+        Expr::Function(function)=>called.push(*function),
+        Expr::Operation{left, right, ..}=>{
+            called_functions(file, *left, called);
+            called_functions(file, *right, called);
+        },
+        Expr::Group(items)=>for item in items {
+            called_functions(file, *item, called);
+        },
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Negate(inner)=>called_functions(file, *inner, called),
+        _=>{},
+    }
+}
+
+/// Checks if a chain of calls in the call graph leads from `from` to `to`. Functions that aren't
+/// in the graph are not followed.
+fn reaches(
+    call_graph: &FnvHashMap<FunctionIndex, Vec<FunctionIndex>>,
+    from: FunctionIndex,
+    to: FunctionIndex,
+)->bool {
+    let mut visited = FnvHashSet::default();
+    let mut stack = vec![from];
+
+    while let Some(function) = stack.pop() {
+        for called in call_graph.get(&function).into_iter().flatten() {
+            if *called == to {
+                return true;
+            }
+            if visited.insert(*called) {
+                stack.push(*called);
+            }
+        }
+    }
+
+    return false;
+}
+
+/// Checks if copying the expression is the same as evaluating it once.
+fn is_trivial(file: &File, expr: ExprIndex)->bool {
+    match file.get_expr(expr) {
+        Expr::Var(_)|
+            Expr::Number(_)|
+            Expr::String(_)|
+            Expr::Char(_)|
+            Expr::None=>true,
+        _=>false,
+    }
+}
+
+/// Copies the body with the arguments in place of the parameters. The copied expressions are
+/// patched onto the call, and the copy of `expr` itself is returned so it can replace the call.
+/// Arguments used once are moved instead of copied.
+fn copy_expr(
+    file: &mut File,
+    expr: ExprIndex,
+    substitutions: &FnvHashMap<VarIndex, ExprIndex>,
+    uses: &FnvHashMap<VarIndex, usize>,
+    call: ExprIndex,
+)->Expr {
+    let copy = |file: &mut File, expr|{
+        let copied = copy_expr(file, expr, substitutions, uses, call);
+        let index = file.patch_expr(copied, call);
+        if let Some(span) = file.name_spans.get(&expr).cloned() {
+            file.name_spans.insert(index, span);
+        }
+        index
+    };
+
+    match file.get_expr(expr).clone() {
+        Expr::Var(var) if substitutions.contains_key(&var)=>{
+            let arg = substitutions[&var];
+            if uses[&var] == 1 {
+                mem::replace(file.get_mut_expr(arg), Expr::Skip)
+            } else {
+                file.get_expr(arg).clone()
+            }
+        },
+        Expr::Operation{left, right, op}=>Expr::Operation {
+            left: copy(file, left),
+            right: copy(file, right),
+            op,
+        },
+        Expr::Group(items)=>Expr::Group(items.into_iter()
+            .map(|item|copy(file, item))
+            .collect()
+        ),
+        Expr::Field{left, name}=>Expr::Field {
+            left: copy(file, left),
+            name,
+        },
+        Expr::Cast{expr, ty}=>Expr::Cast {
+            expr: copy(file, expr),
+            ty,
+        },
+        Expr::TupleIndex{left, index}=>Expr::TupleIndex {
+            left: copy(file, left),
+            index,
+        },
+        Expr::Negate(inner)=>Expr::Negate(copy(file, inner)),
+        other=>other,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::StringInterner;
+    use super::*;
+
+    /// The initializer of the last variable named `name`.
+    fn init<'a>(file: &'a File, interner: &StringInterner, name: &str)->&'a Expr {
+        let var = file.vars.iter()
+            .rfind(|var|interner.get_string(var.name) == name)
+            .unwrap();
+        file.get_expr(var.init.unwrap())
+    }
+
+    #[test]
+    fn tiny_funcs_are_inlined() {
+        let source = "func add (a, b)\n    a + b\nlet x = add (1, 2)\n";
+        let (file, interner) = File::parse(source).unwrap();
+
+        assert!(matches!(init(&file, &interner, "x"), Expr::Operation{op: Operator::Add, ..}));
+    }
+
+    #[test]
+    fn mutually_recursive_funcs_are_not_inlined() {
+        let source = "func f x\n    g x\nfunc g x\n    f x\nlet y = f 1\n";
+        let (file, interner) = File::parse(source).unwrap();
+
+        let Expr::Operation{left, op: Operator::Apply, ..} = init(&file, &interner, "y") else {
+            panic!("`f 1` should still be a call");
+        };
+        let Expr::Function(called) = file.get_expr(*left) else {
+            panic!("`f` should be resolved to the function");
+        };
+        assert_eq!(interner.get_string(file.get_function(*called).name), "f");
+    }
+}
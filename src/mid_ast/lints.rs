@@ -0,0 +1,420 @@
+// lints and other analyses that only report diagnostics
+
+
+use crate::StringInterner;
+use super::{
+    tree::*,
+    folding::{
+        fold,
+        Constant,
+    },
+    effects::{
+        callee,
+        calls_proc,
+    },
+};
+
+
+/// Runs every lint on the file and appends the diagnostics to it.
+pub fn run_all(file: &mut File, interner: &StringInterner) {
+    let mut diagnostics = Vec::new();
+
+    diagnostics.append(&mut zero_division(file, interner));
+    diagnostics.append(&mut constant_conditions(file));
+    diagnostics.append(&mut exclusive_borrows(file, interner));
+    diagnostics.append(&mut discarded_func_results(file, interner));
+    diagnostics.append(&mut use_after_disown(file, interner));
+    diagnostics.append(&mut shadowed_params(file, interner));
+    diagnostics.append(&mut chained_comparisons(file, interner));
+
+    file.diagnostics.append(&mut diagnostics);
+}
+
+/// Flags division and remainder operations by a literal `0`, and notes divisors that are variables
+/// which could be zero at runtime.
+pub fn zero_division(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (_, expr) in file.all_exprs() {
+        let (right, op) = match expr {
+            Expr::Operation{right, op: op@(Operator::Div|Operator::Rem), ..}=>(*right, op),
+            _=>continue,
+        };
+        let op_str = match op {
+            Operator::Div=>"Division",
+            _=>"Remainder",
+        };
+
+        match file.get_expr(right) {
+            Expr::Number(0)=>diagnostics.push(Diagnostic::error(format!(
+                "{op_str} by zero",
+            ))),
+            Expr::RawVar(name)=>diagnostics.push(Diagnostic::note(format!(
+                "{op_str} by variable `{}` which could be zero",
+                interner.get_string(*name),
+            ))),
+            Expr::Var(var)=>diagnostics.push(Diagnostic::note(format!(
+                "{op_str} by variable `{}` which could be zero",
+                interner.get_string(file.get_var(*var).name),
+            ))),
+            _=>{},
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Flags `if` and `cond` conditions that always evaluate to the same boolean.
+pub fn constant_conditions(file: &File)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (_, stmt) in file.all_stmts() {
+        let conditions = match stmt {
+            Stmt::IfElse{condition, ..}=>std::slice::from_ref(condition),
+            Stmt::Conditional{conditions, ..}=>conditions.as_slice(),
+            _=>continue,
+        };
+
+        for condition in conditions {
+            if let Some(Constant::Bool(value)) = fold(file, *condition) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "Condition is always `{value}`",
+                )));
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Flags variables that are mutably borrowed while another borrow of them could still be live.
+///
+/// Liveness is approximated with statement order: a borrow stored directly in a `let` lives until
+/// the end of that variable's scope, and any other borrow only lives for its own statement.
+pub fn exclusive_borrows(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (index, var) in file.vars.iter().enumerate() {
+        if var.mut_borrows.len() == 0 {
+            continue;
+        }
+        let index = VarIndex(index);
+
+        let shared = var.borrows
+            .iter()
+            .map(|site|(*site, false));
+        let borrows = var.mut_borrows
+            .iter()
+            .map(|site|(*site, true))
+            .chain(shared)
+            .map(|(site, mutable)|(site, borrow_end(file, site), mutable))
+            .collect::<Vec<_>>();
+
+        for (i, (site, end, mutable)) in borrows.iter().enumerate() {
+            if !mutable {
+                break;
+            }
+
+            for (other_site, other_end, other_mutable) in &borrows[i + 1..] {
+                let overlaps = site.root <= other_end.root && other_site.root <= end.root;
+                if !overlaps {
+                    continue;
+                }
+
+                let other_kind = match other_mutable {
+                    true=>"mutably borrowed",
+                    false=>"borrowed",
+                };
+                let diagnostic = Diagnostic::error(format!(
+                    "Cannot mutably borrow `{}` because it is also {other_kind}{}",
+                    interner.get_string(var.name),
+                    file.site_line(index, *other_site),
+                ));
+                diagnostics.push(match file.site_span(index, *site) {
+                    Some(span)=>diagnostic.with_span(span),
+                    None=>diagnostic,
+                });
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// The last statement a borrow made in the statement at `site` could be live for.
+fn borrow_end(file: &File, site: StmtIndex)->StmtIndex {
+    if let Stmt::VarDef(holder) = file.get_stmt(site) {
+        let holder = file.get_var(*holder);
+        let stored = holder.init
+            .map(|init|file.get_expr(init))
+            .map(|init|matches!(init, Expr::Borrow(_)|Expr::BorrowMut(_)))
+            .unwrap_or(false);
+
+        if stored {
+            return file.get_scope(holder.in_scope).last;
+        }
+    }
+
+    return site;
+}
+
+/// Flags `func` calls used as statements. `func`s have no side effects, so calling one without
+/// using the result does nothing. `proc` calls, and `func` calls with `proc` calls in their
+/// arguments, are left alone.
+pub fn discarded_func_results(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (index, stmt) in file.all_stmts() {
+        let expr = match stmt {
+            Stmt::Expr(expr) if !file.discarded.contains(&index)=>*expr,
+            _=>continue,
+        };
+
+        if !matches!(file.get_expr(expr), Expr::Operation{op: Operator::Apply, ..}) {
+            continue;
+        }
+        if calls_proc(file, expr) {
+            continue;
+        }
+
+        if let Expr::Function(function) = file.get_expr(callee(file, expr)) {
+            diagnostics.push(Diagnostic::warning(format!(
+                "The result of `func` `{}` is unused, so the call does nothing",
+                interner.get_string(file.get_function(*function).name),
+            )));
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Flags every use, borrow, deref, and assignment of a variable after the statement that disowns
+/// it. Each site is reported separately.
+pub fn use_after_disown(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (index, var) in file.vars.iter().enumerate() {
+        let index = VarIndex(index);
+        let disown = match var.disown {
+            Some(disown)=>disown,
+            None=>continue,
+        };
+
+        let sites = [
+            (&var.uses, "used"),
+            (&var.borrows, "borrowed"),
+            (&var.mut_borrows, "mutably borrowed"),
+            (&var.derefs, "dereferenced"),
+            (&var.assigns, "assigned"),
+        ];
+        let mut misuses = sites.into_iter()
+            .flat_map(|(sites, kind)|sites.iter().map(move |site|(*site, kind)))
+            .filter(|(site, _)|*site > disown)
+            .collect::<Vec<_>>();
+        misuses.sort_by_key(|(site, _)|*site);
+
+        for (site, kind) in misuses {
+            let diagnostic = Diagnostic::error(format!(
+                "`{}` is {kind} after it was disowned{}",
+                interner.get_string(var.name),
+                file.site_line(index, disown),
+            ));
+            diagnostics.push(match file.site_span(index, site) {
+                Some(span)=>diagnostic.with_span(span),
+                None=>diagnostic,
+            });
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Flags `let`s in a function body that shadow one of the function's parameters, including in
+/// nested blocks and functions. Parameters of nested functions are not `let`s, so they are left
+/// alone.
+pub fn shadowed_params(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let lets = file.vars.iter()
+        .filter(|var|match file.get_stmt(var.definition) {
+            Stmt::VarDef(def)=>file.get_var(*def).name == var.name,
+            Stmt::Destructure{vars, ..}=>vars.iter().any(|def|file.get_var(*def).name == var.name),
+            _=>false,
+        })
+        .collect::<Vec<_>>();
+
+    for function in file.functions.iter() {
+        for param in function.params.iter().map(|param|file.get_var(*param)) {
+            let shadows = lets.iter()
+                // a parameter defined at a `let x` statement is not a `let` itself
+                .filter(|var|var.name == param.name && !std::ptr::eq(**var, param))
+                .filter(|var|{
+                    var.in_scope == function.block.scope ||
+                        file.is_ancestor(function.block.scope, var.in_scope)
+                });
+
+            for var in shadows {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "`let {name}` shadows the parameter `{name}` of `{}` on line {}",
+                    interner.get_string(function.name),
+                    file.line_of(param.span.start),
+                    name = interner.get_string(param.name),
+                )).with_span(var.span.clone()));
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Flags comparisons whose left side is also a comparison, like `a < b < c`. Comparisons are
+/// left-associative, so that compares the boolean result of `a < b` to `c`.
+pub fn chained_comparisons(file: &File, interner: &StringInterner)->Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (index, expr) in file.all_exprs() {
+        let (left, right, op) = match expr {
+            Expr::Operation{left, right, op} if op.is_comparison()=>(*left, *right, op),
+            _=>continue,
+        };
+        let middle = match file.get_expr(left) {
+            Expr::Operation{right: middle, op: left_op, ..} if left_op.is_comparison()=>*middle,
+            _=>continue,
+        };
+
+        diagnostics.push(Diagnostic::warning(format!(
+            "`{}` compares the result of `{}` to `{}`. Did you mean `{} and {} {} {}`?",
+            file.expr_to_string(index, interner),
+            file.expr_to_string(left, interner),
+            file.expr_to_string(right, interner),
+            file.expr_to_string(left, interner),
+            file.expr_to_string(middle, interner),
+            op.symbol(),
+            file.expr_to_string(right, interner),
+        )));
+    }
+
+    return diagnostics;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every diagnostic from running the whole pipeline on the source.
+    fn diagnostics(source: &str)->Vec<Diagnostic> {
+        match File::parse(source) {
+            Ok((file, _))=>file.diagnostics,
+            Err(ProgramError::Diagnostics(diagnostics))=>diagnostics,
+            Err(ProgramError::Parse(_))=>panic!("`{source}` should parse"),
+        }
+    }
+
+    #[test]
+    fn zero_divisors_are_flagged() {
+        let zero = |diagnostic: &Diagnostic|diagnostic.msg.ends_with("by zero");
+
+        assert!(diagnostics("func f x\n    let y = x / 0\n    y\n").iter().any(zero));
+        assert!(diagnostics("func f x\n    let y = x % 0\n    y\n").iter().any(zero));
+        assert!(!diagnostics("func f x\n    let y = x / 2\n    y\n").iter().any(zero));
+    }
+
+    #[test]
+    fn constant_conditions_are_warned_about() {
+        let constant = |diagnostic: &Diagnostic|diagnostic.msg.starts_with("Condition is always");
+
+        let always = diagnostics("func f x\n    if 1 < 2\n        print x\n    x\n");
+        assert!(always.iter().any(|d|d.msg == "Condition is always `true`"));
+        let variable = diagnostics("func f x\n    if x < 2\n        print x\n    x\n");
+        assert!(!variable.iter().any(constant));
+    }
+
+    #[test]
+    fn overlapping_borrows_point_at_both_sites() {
+        let borrowed = |diagnostic: &Diagnostic|diagnostic.msg.starts_with("Cannot mutably borrow");
+
+        let ok = "let mut x = 1\nlet r = &mut x\n";
+        assert!(!diagnostics(ok).iter().any(borrowed));
+
+        let source = "let mut x = 1\nlet r = &x\nlet m = &mut x\n";
+        let errors = diagnostics(source)
+            .into_iter()
+            .filter(borrowed)
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].msg,
+            "Cannot mutably borrow `x` because it is also borrowed on line 2",
+        );
+        assert_eq!(errors[0].span.clone().map(|span|&source[span]), Some("x"));
+        assert_eq!(errors[0].span.as_ref().unwrap().start, source.rfind('x').unwrap());
+    }
+
+    #[test]
+    fn explicit_discards_are_not_warned_about() {
+        let unused = |diagnostic: &Diagnostic|diagnostic.msg.ends_with("so the call does nothing");
+
+        // two statements, so the calls aren't inlined away
+        let func = "func f x\n    let y = x + 1\n    y\n";
+        assert!(diagnostics(&format!("{func}f 1\n")).iter().any(unused));
+        assert!(!diagnostics(&format!("{func}let _ = f 1\n")).iter().any(unused));
+
+        // a `proc` is called for what it does, so its result can be dropped
+        assert!(!diagnostics("proc p x\n    print x\np 1\n").iter().any(unused));
+    }
+
+    #[test]
+    fn each_use_after_disown_is_reported() {
+        let source = "let mut x = 1\ndisown x\nlet y = x + 1\nset x = 2\n";
+        let errors = diagnostics(source)
+            .into_iter()
+            .filter(|d|d.msg.contains("after it was disowned"))
+            .map(|d|(d.msg.into_owned(), d.span.map(|span|span.start)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(errors, [
+            ("`x` is used after it was disowned on line 2".to_string(), Some(31)),
+            ("`x` is assigned after it was disowned on line 2".to_string(), Some(41)),
+        ]);
+    }
+
+    #[test]
+    fn cond_arms_before_a_disown_are_not_after_it() {
+        let source = "let x = 1\nlet c = 1 < 2\ncond\n    c => print x\ndisown x\n";
+        let (file, interner) = File::parse(source).unwrap();
+        assert!(!file.diagnostics.iter().any(|d|d.msg.contains("after it was disowned")));
+
+        // `x` is live until the `disown`, and not past it
+        let x = file.vars.iter().find(|var|interner.get_string(var.name) == "x").unwrap();
+        assert_eq!(x.liveness().1, x.disown.unwrap());
+    }
+
+    #[test]
+    fn shadowed_params_point_at_the_let() {
+        let source = "func f x\n    let x = 2\n    let y = x\n    y\n";
+        let warnings = diagnostics(source)
+            .into_iter()
+            .filter(|d|d.msg.contains("shadows the parameter"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].msg, "`let x` shadows the parameter `x` of `f` on line 1");
+        assert_eq!(warnings[0].span, Some(17..18));
+    }
+
+    #[test]
+    fn chained_comparisons_are_warned_about() {
+        let chained = |d: &Diagnostic|d.msg.contains("compares the result of");
+
+        let warnings = diagnostics("func f (a, b, c)\n    let y = a < b < c\n    y\n");
+        let warning = warnings.iter().find(|d|chained(d)).unwrap();
+        assert_eq!(
+            warning.msg,
+            "`(a < b) < c` compares the result of `a < b` to `c`. Did you mean `a < b and b < c`?",
+        );
+
+        let source = "func f (a, b, c)\n    let y = a < b and b < c\n    y\n";
+        assert!(!diagnostics(source).iter().any(chained));
+    }
+}
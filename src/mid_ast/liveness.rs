@@ -0,0 +1,134 @@
+// variable use tracking for liveness
+
+
+use super::tree::*;
+
+
+#[derive(Copy, Clone)]
+enum UseKind {
+    Use,
+    Deref,
+    Assign,
+    Disown,
+}
+
+
+/// Records every statement that uses, derefs, assigns, or disowns each variable. Borrows are
+/// already recorded during conversion.
+pub fn record_uses(file: &mut File) {
+    let mut uses = Vec::new();
+
+    for (index, stmt) in file.all_stmts() {
+        let mut exprs = Vec::new();
+
+        match stmt {
+            Stmt::VarDef(var)=>exprs.extend(file.get_var(*var).init),
+            Stmt::Destructure{data, ..}|
+                Stmt::Expr(data)=>exprs.push(*data),
+            Stmt::VarSet{data, var, ..}=>{
+                uses.push((*var, index, UseKind::Assign));
+                exprs.push(*data);
+            },
+            Stmt::VarSetMulti{data, vars, ..}=>{
+                for var in vars {
+                    uses.push((*var, index, UseKind::Assign));
+                }
+                exprs.push(*data);
+            },
+            Stmt::IfElse{condition, ..}=>exprs.push(*condition),
+            Stmt::Conditional{conditions, ..}=>exprs.extend(conditions),
+            Stmt::Disown(data)=>match file.get_expr(*data) {
+                Expr::Var(var)=>uses.push((*var, index, UseKind::Disown)),
+                _=>exprs.push(*data),
+            },
+            Stmt::Return{data, ..}=>exprs.extend(*data),
+            Stmt::JumpTo(_)|Stmt::Skip=>{},
+        }
+
+        for expr in exprs {
+            expr_uses(file, expr, index, &mut uses);
+        }
+    }
+
+    for (var, index, kind) in uses {
+        // targets that were never defined were already reported during conversion
+        if var == VarIndex::invalid() {
+            continue;
+        }
+
+        let var = file.get_mut_var(var);
+        match kind {
+            UseKind::Use=>var.uses.push(index),
+            UseKind::Deref=>var.derefs.push(index),
+            UseKind::Assign=>var.assigns.push(index),
+            UseKind::Disown=>var.disown = Some(index),
+        }
+    }
+}
+
+fn expr_uses(
+    file: &File,
+    expr: ExprIndex,
+    stmt: StmtIndex,
+    uses: &mut Vec<(VarIndex, StmtIndex, UseKind)>,
+) {
+    match file.get_expr(expr) {
+        Expr::Var(var)=>uses.push((*var, stmt, UseKind::Use)),
+        Expr::Deref(inner)=>match file.get_expr(*inner) {
+            Expr::Var(var)=>uses.push((*var, stmt, UseKind::Deref)),
+            _=>expr_uses(file, *inner, stmt, uses),
+        },
+        // borrows of variables are recorded during conversion
+        Expr::Borrow(inner)|Expr::BorrowMut(inner)=>match file.get_expr(*inner) {
+            Expr::Var(_)=>{},
+            _=>expr_uses(file, *inner, stmt, uses),
+        },
+        Expr::Operation{left, right, ..}=>{
+            expr_uses(file, *left, stmt, uses);
+            expr_uses(file, *right, stmt, uses);
+        },
+        Expr::Group(items)|Expr::List(items)|Expr::Partial{args: items, ..}=>for item in items {
+            expr_uses(file, *item, stmt, uses);
+        },
+        Expr::Field{left: inner, ..}|
+            Expr::Cast{expr: inner, ..}|
+            Expr::TupleIndex{left: inner, ..}|
+            Expr::Negate(inner)|
+            Expr::Heap(inner)|
+            Expr::Stack(inner)=>expr_uses(file, *inner, stmt, uses),
+        Expr::RawVar(_)|
+            Expr::Number(_)|
+            Expr::String(_)|
+            Expr::Char(_)|
+            Expr::None|
+            Expr::TypeOf(_)|
+            Expr::Function(_)|
+            Expr::Skip=>{},
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undefined_targets_are_skipped() {
+        let Err(ProgramError::Diagnostics(diagnostics)) = File::parse("set missing = 1\n") else {
+            panic!("setting an undefined variable should be an error");
+        };
+
+        assert!(diagnostics.iter().any(|d|d.msg == "Undefined variable `missing`"));
+    }
+
+    #[test]
+    fn uses_are_recorded() {
+        let (file, interner) = File::parse("let mut x = 1\nset x = 2\nlet y = x + 1\n").unwrap();
+
+        let x = file.vars.iter()
+            .find(|var|interner.get_string(var.name) == "x")
+            .unwrap();
+        assert_eq!(x.assigns.len(), 1);
+        assert_eq!(x.uses.len(), 1);
+    }
+}
@@ -0,0 +1,162 @@
+// lowering of high-level control flow into simpler statements
+
+
+use std::mem;
+use super::tree::*;
+
+
+/// Lowers every `cond` into a chain of `if`/`else` statements. Conditions are tested top to
+/// bottom, and every branch falls through to the conditional's `last` statement.
+/// ```
+/// cond
+///     a => x
+///     b => y
+///
+/// // becomes
+///
+/// if a
+///     x
+/// else
+///     if b
+///         y
+/// ```
+pub fn lower_conditionals(file: &mut File) {
+    for scope in 0..file.scopes.len() {
+        let scope = ScopeIndex(scope);
+        let conditionals = file.scope_stmts(scope)
+            .filter(|(_, stmt)|matches!(stmt, Stmt::Conditional{..}))
+            .map(|(index, _)|index)
+            .collect::<Vec<_>>();
+
+        for index in conditionals {
+            lower_conditional(file, scope, index);
+        }
+    }
+}
+
+fn lower_conditional(file: &mut File, scope: ScopeIndex, index: StmtIndex) {
+    let (conditions, actions, mut default, last, span) = match mem::replace(
+        file.get_mut_stmt(index),
+        Stmt::Skip,
+    ) {
+        Stmt::Conditional{conditions, actions, default, last, span}=>{
+            (conditions, actions, default, last, span)
+        },
+        _=>unreachable!("Conditional statement"),
+    };
+
+    let count = conditions.len();
+    let mut target = index;
+
+    for (i, (condition, action)) in conditions.into_iter().zip(actions).enumerate() {
+        let block = action_block(file, scope, index, action);
+
+        // the next arm is placed in the else block of this one, and the last arm gets the default
+        let else_block = if i + 1 < count {
+            Some(single_stmt_block(file, scope, index, Stmt::Skip))
+        } else {
+            default.take().map(|action|action_block(file, scope, index, action))
+        };
+        let next_target = else_block.as_ref().map(|block|block.first);
+
+        *file.get_mut_stmt(target) = Stmt::IfElse {
+            condition,
+            block,
+            else_block,
+            last,
+            span: span.clone(),
+        };
+
+        match next_target {
+            Some(next) if i + 1 < count=>target = next,
+            _=>break,
+        }
+    }
+}
+
+fn action_block(
+    file: &mut File,
+    scope: ScopeIndex,
+    conditional: StmtIndex,
+    action: ConditionalAction,
+)->Block {
+    match action {
+        ConditionalAction::Scope(block)=>block,
+        ConditionalAction::Expr(expr)=>{
+            single_stmt_block(file, scope, conditional, Stmt::Expr(expr))
+        },
+    }
+}
+
+/// Creates a new block containing only the given statement. It is a patch of the conditional, so
+/// it stays between the statements before and after the conditional.
+fn single_stmt_block(
+    file: &mut File,
+    parent: ScopeIndex,
+    conditional: StmtIndex,
+    stmt: Stmt,
+)->Block {
+    let index = file.patch_stmt(stmt, conditional);
+    let scope = file.add_scope(Scope::new(Some(parent), index, index));
+    file.get_mut_scope(parent).scopes.push(scope);
+
+    Block {
+        first: index,
+        last: index,
+        scope,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::{
+        *,
+        super::conversion::convert_parse_tree,
+    };
+
+    #[test]
+    fn cond_arms_become_nested_ifs() {
+        let source = concat!(
+            "func f (a, b, c)\n",
+            "    cond\n",
+            "        a => print 1\n",
+            "        b => print 2\n",
+            "        c => print 3\n",
+            "    print 4\n",
+        );
+        let (stmts, interner, _) = Parser::new(source).parse_all().unwrap();
+        let mut file = convert_parse_tree(stmts, source, &interner, &[]);
+        let (cond, cond_last) = file.all_stmts()
+            .find_map(|(index, stmt)|match stmt {
+                Stmt::Conditional{last, ..}=>Some((index, *last)),
+                _=>None,
+            })
+            .unwrap();
+        lower_conditionals(&mut file);
+
+        assert!(!file.all_stmts().any(|(_, stmt)|matches!(stmt, Stmt::Conditional{..})));
+
+        let ifs = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::IfElse{else_block, last, ..}=>Some((else_block.is_some(), *last)),
+                _=>None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ifs.len(), 3);
+
+        // every arm but the last has the next one in its else block
+        let elses = ifs.iter().map(|(has_else, _)|*has_else).collect::<Vec<_>>();
+        assert_eq!(elses, [true, true, false]);
+
+        // and they all fall through to the end of the `cond`
+        assert!(ifs.iter().all(|(_, last)|*last == cond_last));
+
+        // the arms are patches of the `cond`, so they stay before the `print 4` after it
+        let arms = file.stmt_with_patches(cond.root)
+            .filter(|(_, stmt)|matches!(stmt, Stmt::Expr(_)))
+            .count();
+        assert_eq!(arms, 3);
+    }
+}
@@ -3,6 +3,18 @@
 
 pub mod tree;
 pub mod conversion;
+pub mod assignment;
+pub mod cleanup;
+pub mod diagnostic;
+pub mod effects;
+pub mod escape;
+pub mod folding;
+pub mod inlining;
+pub mod lints;
+pub mod liveness;
+pub mod lowering;
+pub mod scopes;
+pub mod verify;
 
 
 
@@ -0,0 +1,102 @@
+// scope tree simplification
+
+
+use fnv::FnvHashSet;
+use super::tree::*;
+
+
+/// Merges every `scope` block that doesn't declare any variables or functions into its parent,
+/// so there are fewer scopes to walk through when looking names up. Only scopes that no `if` or
+/// function body uses as its block are merged, because those have to stay separate to run
+/// conditionally. Labeled scopes and `loop` bodies are kept too, since `break` and `continue`
+/// name them.
+///
+/// Statements are never moved, so `JumpTo` targets stay the same. Merged scopes are left in
+/// `File::scopes` so the other indices stay valid, but they have no parent and no parent lists
+/// them as a child.
+pub fn merge_empty_scopes(file: &mut File) {
+    let blocks = block_scopes(file);
+
+    for index in 0..file.scopes.len() {
+        let scope = ScopeIndex(index);
+        let scope_ref = file.get_scope(scope);
+
+        let parent = match scope_ref.parent {
+            Some(parent) if !blocks.contains(&scope)=>parent,
+            _=>continue,
+        };
+        if !scope_ref.vars.is_empty() || !scope_ref.functions.is_empty() {
+            continue;
+        }
+        if scope_ref.label.is_some() || scope_ref.is_loop {
+            continue;
+        }
+
+        let scope_ref = file.get_mut_scope(scope);
+        scope_ref.parent = None;
+        let children = std::mem::take(&mut scope_ref.scopes);
+
+        for child in &children {
+            file.get_mut_scope(*child).parent = Some(parent);
+        }
+
+        // the children take the merged scope's place so the parent's children stay in order
+        let siblings = &mut file.get_mut_scope(parent).scopes;
+        if let Some(position) = siblings.iter().position(|s|*s == scope) {
+            siblings.splice(position..position + 1, children);
+        }
+    }
+}
+
+/// Every scope that is the block of a statement or function.
+fn block_scopes(file: &File)->FnvHashSet<ScopeIndex> {
+    let mut scopes = FnvHashSet::default();
+
+    for (_, stmt) in file.all_stmts() {
+        match stmt {
+            Stmt::IfElse{block, else_block, ..}=>{
+                scopes.insert(block.scope);
+                scopes.extend(else_block.iter().map(|block|block.scope));
+            },
+            Stmt::Conditional{actions, default, ..}=>{
+                for action in actions.iter().chain(default) {
+                    if let ConditionalAction::Scope(block) = action {
+                        scopes.insert(block.scope);
+                    }
+                }
+            },
+            _=>{},
+        }
+    }
+
+    for function in &file.functions {
+        scopes.insert(function.block.scope);
+    }
+
+    return scopes;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_empty_scopes_are_merged() {
+        let source = concat!(
+            "let mut x = 1\n",
+            "scope\n    set x = 2\n",
+            "scope\n    let y = 3\n",
+            "scope 'named\n    set x = 4\n",
+            "loop\n    if x > 5\n        break\n    set x = x + 1\n",
+        );
+        let (file, _) = File::parse(source).unwrap();
+
+        let kept = file.get_scope(file.root_scope).scopes
+            .iter()
+            .map(|scope|file.get_scope(*scope))
+            .map(|scope|(!scope.vars.is_empty(), scope.label.is_some(), scope.is_loop))
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [(true, false, false), (false, true, false), (false, false, true)]);
+    }
+}
@@ -2,49 +2,126 @@
 
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+        Write,
+    },
+    hash::{
+        Hash,
+        Hasher,
+    },
+    ops::Range,
     rc::Rc,
 };
-use fnv::FnvHashMap;
+use fnv::{
+    FnvHashMap,
+    FnvHashSet,
+    FnvHasher,
+};
+use parser_helper::SimpleError;
 use crate::{
+    parser::{
+        Parser,
+        Stmt as PStmt,
+    },
     Index,
     Name,
+    StringInterner,
+};
+use super::{
+    folding::{
+        promote_constants,
+        Constant,
+    },
+    conversion::convert_parse_tree,
+    cleanup::collapse_skips,
+    lowering::lower_conditionals,
+    verify::verify_jumps,
+    scopes::merge_empty_scopes,
+    inlining::inline_functions,
+    effects::mark_barriers,
+    escape::hoist_escaping,
+    liveness::record_uses,
+    assignment::check_assignments,
+    lints,
 };
 
 pub use crate::parser::{
     Operator,
     Pattern,
 };
+pub use super::diagnostic::*;
 
 
-#[derive(Debug)]
+#[derive(Debug, Hash)]
 pub enum Stmt {
     VarDef(VarIndex),
+    /// `let (a, b) = data` evaluates `data` once and binds `vars` in pattern order.
+    Destructure {
+        pattern: Rc<Pattern>,
+        data: ExprIndex,
+        vars: Vec<VarIndex>,
+    },
     VarSet {
         name: Name,
+        /// Where the name is written. Assignments made by `break` with a value have no name.
+        span: Option<Range<usize>>,
         data: ExprIndex,
         var: VarIndex
     },
+    /// Assigns each var from the corresponding element of the tuple `data`.
+    VarSetMulti {
+        names: Vec<Name>,
+        /// Where each name is written.
+        spans: Vec<Range<usize>>,
+        data: ExprIndex,
+        vars: Vec<VarIndex>,
+    },
     IfElse {
         condition: ExprIndex,
         block: Block,
         else_block: Option<Block>,
         last: StmtIndex,
+        /// The span of the `if`, or of the `cond` it was lowered from.
+        span: Range<usize>,
     },
     Conditional {
         conditions: Vec<ExprIndex>,
         actions: Vec<ConditionalAction>,
+        /// The `else` arm, taken if none of the conditions match.
+        default: Option<ConditionalAction>,
         last: StmtIndex,
+        /// The span of the `cond`.
+        span: Range<usize>,
     },
     Disown(ExprIndex),
     Expr(ExprIndex),
-    Return(Option<ExprIndex>),
+    /// Returns from the enclosing function, no matter how deeply nested the statement is.
+    Return {
+        function: FunctionIndex,
+        data: Option<ExprIndex>,
+    },
 
+    /// Jumps to the given statement. `break`s are lowered to this, and target the first statement
+    /// after the scope they break out of.
     JumpTo(StmtIndex),
     Skip,
 }
+impl Stmt {
+    /// Checks if control never continues to the next statement after this one.
+    pub fn is_terminator(&self)->bool {
+        match self {
+            Self::Return{..}|Self::JumpTo(_)=>true,
+            _=>false,
+        }
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Hash)]
 pub enum Expr {
     /// <expr> <op> <expr>
     Operation {
@@ -57,46 +134,148 @@ pub enum Expr {
         left: ExprIndex,
         name: Index,
     },
+    /// <expr> as <type>
+    Cast {
+        expr: ExprIndex,
+        ty: Type,
+    },
     /// '[' <expr> (',' <expr>)+ ','? ']'
     Group(Vec<ExprIndex>),
+    /// `[a, b, c]`. Every element has the same type.
+    List(Vec<ExprIndex>),
+    /// The element at `index` of the tuple `left`. Only produced by conversion.
+    TupleIndex {
+        left: ExprIndex,
+        index: usize,
+    },
     RawVar(Name),
     Number(i64),
     String(Index),
+    Char(char),
     Borrow(ExprIndex),
+    BorrowMut(ExprIndex),
     Deref(ExprIndex),
+    Negate(ExprIndex),
+    /// Data that is always put on the heap.
+    Heap(ExprIndex),
+    /// Data that must stay on the stack.
+    Stack(ExprIndex),
+    /// The type of the expression, which is never evaluated.
+    TypeOf(ExprIndex),
     None,
 
     Var(VarIndex),
     Function(FunctionIndex),
+    /// A function applied to fewer arguments than it takes. Applying it adds to the arguments.
+    Partial {
+        function: FunctionIndex,
+        args: Vec<ExprIndex>,
+    },
     /// Used to convey an optimized-out expression
     Skip,
 }
+impl Expr {
+    /// Checks if the expression can be printed as an operand without parentheses.
+    pub fn is_group(&self)->bool {
+        match self {
+            Self::None|
+                Self::Group(_)|
+                Self::List(_)|
+                Self::String(_)|
+                Self::Char(_)|
+                Self::Number(_)|
+                Self::Field{..}|
+                Self::TupleIndex{..}|
+                Self::RawVar(_)|
+                Self::Var(_)|
+                Self::Function(_)|
+                Self::Skip=>true,
+            _=>false,
+        }
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Hash)]
 pub enum ConditionalAction {
     Expr(ExprIndex),
     Scope(Block),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Ref(Box<Self>),
+    RefMut(Box<Self>),
     Tuple(Vec<Self>),
+    /// Either `None` or a value of the inner type. `None` is the absence of a value, and is not the
+    /// same as unit, which is the empty tuple.
+    Option(Box<Self>),
+    /// Written `[Number]`. Every element has the same type.
+    List(Box<Self>),
+    /// The result of a comparison or `and`/`or`.
+    Bool,
     String,
+    Char,
     Number,
     Undetermined,
 }
+impl Type {
+    /// The type a name refers to when it is written in the source, like in a cast. List types are
+    /// the element type's name in square brackets.
+    pub fn from_name(name: &str)->Option<Self> {
+        if let Some(inner) = name.strip_prefix('[').and_then(|name|name.strip_suffix(']')) {
+            return Self::from_name(inner.trim()).map(|inner|Type::List(Box::new(inner)));
+        }
 
-#[derive(Debug)]
+        match name {
+            "Number"=>Some(Type::Number),
+            "String"=>Some(Type::String),
+            "Char"=>Some(Type::Char),
+            "Bool"=>Some(Type::Bool),
+            "Option"=>Some(Type::Option(Box::new(Type::Undetermined))),
+            _=>None,
+        }
+    }
+
+    /// Checks if a value of type `actual` can be used where this type is expected. An `Option`
+    /// accepts `None` and any value its inner type accepts, but not unit.
+    pub fn accepts(&self, actual: &Self)->bool {
+        match (self, actual) {
+            (Type::Undetermined, _)|(_, Type::Undetermined)=>true,
+            (Type::Option(inner), Type::Option(actual))=>inner.accepts(actual),
+            // an unknown inner type would accept unit, which isn't the same as `None`
+            (Type::Option(inner), Type::Tuple(items)) if items.len() == 0=>**inner == *actual,
+            (Type::Option(inner), actual)=>inner.accepts(actual),
+            (Type::Ref(inner), Type::Ref(actual))|
+                (Type::RefMut(inner), Type::RefMut(actual))|
+                (Type::List(inner), Type::List(actual))=>inner.accepts(actual),
+            (Type::Tuple(items), Type::Tuple(actual))=>{
+                items.len() == actual.len() &&
+                    items.iter().zip(actual).all(|(item, actual)|item.accepts(actual))
+            },
+            _=>self == actual,
+        }
+    }
+
+    pub fn is_numeric(&self)->bool {
+        match self {
+            Type::Number=>true,
+            _=>false,
+        }
+    }
+}
+
+#[derive(Debug, Hash)]
 pub enum MemoryLocation {
     Stack(usize),
     Heap,
+    /// Compile-time constants are inlined at every use, so they never take any memory.
+    Inlined,
     Undetermined,
 }
 
 
 /// The root stmt has a patch index of 0.
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StmtIndex {
     pub root: usize,
     pub patch: usize,
@@ -109,10 +288,31 @@ impl StmtIndex {
             patch: 0,
         }
     }
+
+    /// Checks if the statement is in the range from `first` to `last`. A range that ends on a root
+    /// statement includes the root's patches, since they come after it.
+    pub fn within(&self, first: StmtIndex, last: StmtIndex)->bool {
+        if *self < first || self.root > last.root {
+            return false;
+        }
+
+        return self.root < last.root || last.patch == 0 || self.patch <= last.patch;
+    }
+}
+impl Debug for StmtIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if *self == Self::invalid() {
+            write!(f, "<invalid>")
+        } else if self.patch == 0 {
+            write!(f, "Stmt#{}", self.root)
+        } else {
+            write!(f, "Stmt#{}.{}", self.root, self.patch)
+        }
+    }
 }
 
 /// The root stmt has a patch index of 0.
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct ExprIndex {
     pub root: usize,
     pub patch: usize,
@@ -126,8 +326,19 @@ impl ExprIndex {
         }
     }
 }
+impl Debug for ExprIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if *self == Self::invalid() {
+            write!(f, "<invalid>")
+        } else if self.patch == 0 {
+            write!(f, "Expr#{}", self.root)
+        } else {
+            write!(f, "Expr#{}.{}", self.root, self.patch)
+        }
+    }
+}
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct ScopeIndex(pub usize);
 impl ScopeIndex {
     #[inline]
@@ -135,8 +346,17 @@ impl ScopeIndex {
         ScopeIndex(usize::MAX)
     }
 }
+impl Debug for ScopeIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if *self == Self::invalid() {
+            write!(f, "<invalid>")
+        } else {
+            write!(f, "Scope#{}", self.0)
+        }
+    }
+}
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct FunctionIndex(pub usize);
 impl FunctionIndex {
     #[inline]
@@ -144,8 +364,17 @@ impl FunctionIndex {
         FunctionIndex(usize::MAX)
     }
 }
+impl Debug for FunctionIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if *self == Self::invalid() {
+            write!(f, "<invalid>")
+        } else {
+            write!(f, "Function#{}", self.0)
+        }
+    }
+}
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct VarIndex(pub usize);
 impl VarIndex {
     #[inline]
@@ -153,6 +382,15 @@ impl VarIndex {
         VarIndex(usize::MAX)
     }
 }
+impl Debug for VarIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        if *self == Self::invalid() {
+            write!(f, "<invalid>")
+        } else {
+            write!(f, "Var#{}", self.0)
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Scope {
@@ -164,13 +402,58 @@ pub struct Scope {
     /// A map of `name -> var_list` where `var_list` is a list of var data. Multiple vars with the
     /// same name can exist in the same scope if one is disowned or moved (WIP)
     pub vars: FnvHashMap<Name, Vec<VarIndex>>,
-    /// A map of `name -> function_list` where `function_list` is a map of `pattern -> function`
-    pub functions: FnvHashMap<Name, HashMap<Rc<Pattern>, FunctionIndex>>,
+    /// A map of `name -> function_list` where `function_list` is a map of `pattern -> functions`.
+    /// The functions for a pattern are in dispatch order: guarded definitions first in source
+    /// order, followed by the unguarded fallback.
+    pub functions: FnvHashMap<Name, HashMap<Rc<Pattern>, Vec<FunctionIndex>>>,
 
     pub scopes: Vec<ScopeIndex>,
+
+    /// The label given to a `scope` block, if any.
+    pub label: Option<Name>,
+    /// Whether the scope is the body of a `loop`, which `continue` jumps back to the start of.
+    pub is_loop: bool,
+    /// The enclosing scope. Only the root scope has no parent.
+    pub parent: Option<ScopeIndex>,
+}
+impl Scope {
+    pub fn new(parent: Option<ScopeIndex>, first: StmtIndex, last: StmtIndex)->Self {
+        Scope {
+            first,
+            last,
+            stack_slots: 0,
+            vars: FnvHashMap::default(),
+            functions: FnvHashMap::default(),
+            scopes: Vec::new(),
+            label: None,
+            is_loop: false,
+            parent,
+        }
+    }
 }
 
-#[derive(Debug)]
+impl Hash for Scope {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.first.hash(h);
+        self.last.hash(h);
+        self.stack_slots.hash(h);
+        sorted_entries(&self.vars, |name|name.0).hash(h);
+        for (name, patterns) in sorted_entries(&self.functions, |name|name.0) {
+            name.hash(h);
+
+            // every pattern has at least one function, and each function has only one pattern
+            let mut patterns = patterns.iter().collect::<Vec<_>>();
+            patterns.sort_by_key(|(_, functions)|functions.first().map(|function|function.0));
+            patterns.hash(h);
+        }
+        self.scopes.hash(h);
+        self.label.hash(h);
+        self.is_loop.hash(h);
+        self.parent.hash(h);
+    }
+}
+
+#[derive(Debug, Hash)]
 pub struct VarMetadata {
     pub in_scope: ScopeIndex,
 
@@ -181,14 +464,96 @@ pub struct VarMetadata {
     pub data_type: Type,
 
     pub borrows: Vec<StmtIndex>,
+    pub mut_borrows: Vec<StmtIndex>,
     pub uses: Vec<StmtIndex>,
     pub derefs: Vec<StmtIndex>,
     pub assigns: Vec<StmtIndex>,
 
     pub mem_loc: MemoryLocation,
+    /// Set by `let x = stack data`. It is an error for the variable to be hoisted to the heap.
+    pub stack_pinned: bool,
+    /// The value of the variable if it is known at compile time.
+    pub constant: Option<Constant>,
 
     pub mutable: bool,
     pub name: Name,
+    /// Where the name is written in the definition.
+    pub span: Range<usize>,
+}
+impl VarMetadata {
+    pub fn new(
+        in_scope: ScopeIndex,
+        name: Name,
+        mutable: bool,
+        definition: StmtIndex,
+        init: Option<ExprIndex>,
+        span: Range<usize>,
+    )->Self {
+        VarMetadata {
+            in_scope,
+
+            definition,
+            init,
+            disown: None,
+
+            data_type: Type::Undetermined,
+
+            borrows: Vec::new(),
+            mut_borrows: Vec::new(),
+            uses: Vec::new(),
+            derefs: Vec::new(),
+            assigns: Vec::new(),
+
+            mem_loc: MemoryLocation::Undetermined,
+            stack_pinned: false,
+            constant: None,
+
+            mutable,
+            name,
+            span,
+        }
+    }
+
+    /// The first and last statements the variable is live for, from its definition through every
+    /// use, borrow, deref, assignment, and disown. Statement order approximates execution order.
+    pub fn liveness(&self)->(StmtIndex, StmtIndex) {
+        let sites = self.uses
+            .iter()
+            .chain(&self.borrows)
+            .chain(&self.mut_borrows)
+            .chain(&self.derefs)
+            .chain(&self.assigns)
+            .chain(&self.disown);
+
+        let mut first = self.definition;
+        let mut last = self.definition;
+        for site in sites {
+            first = first.min(*site);
+            last = last.max(*site);
+        }
+
+        return (first, last);
+    }
+
+    /// Checks if both variables could be live at the same time, so they can't share a slot.
+    pub fn live_overlaps(&self, other: &Self)->bool {
+        let (first, last) = self.liveness();
+        let (other_first, other_last) = other.liveness();
+
+        first <= other_last && other_first <= last
+    }
+}
+
+/// How many of each item a `File` has. Useful for finding passes that create too many.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    pub stmts: usize,
+    pub patch_stmts: usize,
+    pub exprs: usize,
+    pub patch_exprs: usize,
+    pub scopes: usize,
+    pub functions: usize,
+    pub vars: usize,
 }
 
 #[derive(Debug)]
@@ -204,8 +569,28 @@ pub struct File {
     pub functions: Vec<FunctionDef>,
 
     pub vars: Vec<VarMetadata>,
+    /// The value of every top-level `const`.
+    pub constants: FnvHashMap<Name, Constant>,
 
     pub root_scope: ScopeIndex,
+    /// The top-level `proc main ()`, if the file has a valid one.
+    pub main: Option<FunctionIndex>,
+
+    /// Statements that could call a `proc`, which nothing may be reordered across.
+    pub barriers: FnvHashSet<StmtIndex>,
+    /// For the argument group of a call, the arguments that could call a `proc`, in source order.
+    /// They must be evaluated in that order, while the other arguments can be evaluated in any
+    /// order. Only groups with more than one such argument are in here.
+    pub sequenced: FnvHashMap<ExprIndex, Vec<ExprIndex>>,
+    /// The statements from `let _ = data`, which throw away the value on purpose.
+    pub discarded: FnvHashSet<StmtIndex>,
+
+    /// Where each name is written in the source, by the expression it was converted to.
+    pub name_spans: FnvHashMap<ExprIndex, Range<usize>>,
+    /// The byte offset each line of the source starts at, for turning spans into line numbers.
+    pub line_starts: Vec<usize>,
+
+    pub diagnostics: Vec<Diagnostic>,
 }
 impl File {
     pub fn new()->Self {
@@ -217,10 +602,236 @@ impl File {
             scopes: Vec::new(),
             functions: Vec::new(),
             vars: Vec::new(),
+            constants: FnvHashMap::default(),
             root_scope: ScopeIndex(0),
+            main: None,
+            barriers: FnvHashSet::default(),
+            sequenced: FnvHashMap::default(),
+            discarded: FnvHashSet::default(),
+            name_spans: FnvHashMap::default(),
+            line_starts: vec![0],
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// An empty file for the given source, so spans into it can be turned into line numbers.
+    pub fn with_source(source: &str)->Self {
+        let bytes = source.as_bytes();
+        let mut line_starts = vec![0];
+        for (i, byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n'=>line_starts.push(i + 1),
+                // a lone `\r` ends a line like the lexer's `Newline`, but `\r\n` is a single break
+                b'\r' if bytes.get(i + 1) != Some(&b'\n')=>line_starts.push(i + 1),
+                _=>{},
+            }
+        }
+
+        return File {
+            line_starts,
+            ..Self::new()
+        };
+    }
+
+    /// Runs the whole pipeline on the source. The interner borrows from the source, so it is
+    /// returned alongside the file instead of being owned by it. Warnings are left in the file's
+    /// diagnostics.
+    pub fn parse<'a>(source: &'a str)->Result<(File, StringInterner<'a>), ProgramError> {
+        Self::parse_with_features(source, &[])
+    }
+
+    /// Like `parse`, but keeps the statements marked with `#[cfg(feature)]` for the given features.
+    pub fn parse_with_features<'a>(
+        source: &'a str,
+        features: &[&str],
+    )->Result<(File, StringInterner<'a>), ProgramError> {
+        let (stmts, interner, warnings) = Parser::new(source)
+            .parse_all()
+            .map_err(ProgramError::Parse)?;
+
+        let file = Self::from_parse_tree(stmts, warnings, source, &interner, features);
+        if file.has_errors() {
+            return Err(ProgramError::Diagnostics(file.diagnostics));
+        }
+
+        return Ok((file, interner));
+    }
+
+    /// Converts the parsed statements and runs every pass on them. The parser's warnings are added
+    /// to the diagnostics, and errors are left in them instead of being returned.
+    pub fn from_parse_tree<'a>(
+        stmts: Vec<PStmt>,
+        warnings: Vec<SimpleError<Cow<'static, str>>>,
+        source: &str,
+        interner: &'a StringInterner<'a>,
+        features: &'a [&'a str],
+    )->File {
+        let mut file = convert_parse_tree(stmts, source, interner, features);
+        for warning in warnings {
+            file.diagnostics.push(Diagnostic::warning(warning.msg).with_span(warning.span));
+        }
+        lower_conditionals(&mut file);
+        verify_jumps(&mut file);
+        merge_empty_scopes(&mut file);
+        inline_functions(&mut file);
+        mark_barriers(&mut file);
+        record_uses(&mut file);
+        check_assignments(&mut file, interner);
+        promote_constants(&mut file);
+        hoist_escaping(&mut file, interner);
+        collapse_skips(&mut file);
+        lints::run_all(&mut file, interner);
+
+        return file;
+    }
+
+    /// The function the program starts at when it is run as an executable.
+    #[inline]
+    pub fn entry_point(&self)->Option<FunctionIndex> {
+        self.main
+    }
+
+    /// The line the byte offset is on, starting from 1.
+    pub fn line_of(&self, offset: usize)->usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line)=>line + 1,
+            Err(line)=>line,
+        }
+    }
+
+    /// Where the statement at `site` names the variable, so a diagnostic about a use of the
+    /// variable can point at it. Returns `None` if the name isn't written there.
+    pub fn site_span(&self, var: VarIndex, site: StmtIndex)->Option<Range<usize>> {
+        let exprs = match self.get_stmt(site) {
+            Stmt::VarSet{var: target, span: Some(span), ..} if *target == var=>{
+                return Some(span.clone());
+            },
+            Stmt::VarSetMulti{vars, spans, ..} if vars.contains(&var)=>{
+                let position = vars.iter().position(|target|*target == var)?;
+                return spans.get(position).cloned();
+            },
+            Stmt::VarDef(def)=>self.get_var(*def).init.into_iter().collect(),
+            Stmt::Destructure{data, ..}|
+                Stmt::VarSet{data, ..}|
+                Stmt::VarSetMulti{data, ..}|
+                Stmt::Disown(data)|
+                Stmt::Expr(data)=>vec![*data],
+            Stmt::IfElse{condition, ..}=>vec![*condition],
+            Stmt::Conditional{conditions, actions, default, ..}=>{
+                let actions = actions.iter().chain(default).filter_map(|action|match action {
+                    ConditionalAction::Expr(expr)=>Some(*expr),
+                    ConditionalAction::Scope(_)=>None,
+                });
+                conditions.iter().copied().chain(actions).collect()
+            },
+            Stmt::Return{data, ..}=>data.iter().copied().collect(),
+            Stmt::JumpTo(_)|Stmt::Skip=>Vec::new(),
+        };
+
+        exprs.into_iter().find_map(|expr|self.name_span(var, expr))
+    }
+
+    /// The span of the first place the expression names the variable.
+    fn name_span(&self, var: VarIndex, expr: ExprIndex)->Option<Range<usize>> {
+        match self.get_expr(expr) {
+            Expr::Var(v) if *v == var=>self.name_spans.get(&expr).cloned(),
+            Expr::Operation{left, right, ..}=>{
+                self.name_span(var, *left).or_else(||self.name_span(var, *right))
+            },
+            Expr::Group(items)|Expr::List(items)|Expr::Partial{args: items, ..}=>{
+                items.iter().find_map(|item|self.name_span(var, *item))
+            },
+            Expr::Field{left: inner, ..}|
+                Expr::Cast{expr: inner, ..}|
+                Expr::TupleIndex{left: inner, ..}|
+                Expr::Borrow(inner)|
+                Expr::BorrowMut(inner)|
+                Expr::Deref(inner)|
+                Expr::Negate(inner)|
+                Expr::Heap(inner)|
+                Expr::Stack(inner)|
+                Expr::TypeOf(inner)=>self.name_span(var, *inner),
+            Expr::Var(_)|
+                Expr::RawVar(_)|
+                Expr::Number(_)|
+                Expr::String(_)|
+                Expr::Char(_)|
+                Expr::None|
+                Expr::Function(_)|
+                Expr::Skip=>None,
+        }
+    }
+
+    /// The span of the name the expression is or starts with, like `f` in `f x + 1`, so a
+    /// diagnostic about the expression can point at it.
+    pub fn leading_span(&self, expr: ExprIndex)->Option<Range<usize>> {
+        if let Some(span) = self.name_spans.get(&expr) {
+            return Some(span.clone());
+        }
+
+        match self.get_expr(expr) {
+            Expr::Operation{left: inner, ..}|
+                Expr::Field{left: inner, ..}|
+                Expr::Cast{expr: inner, ..}|
+                Expr::TupleIndex{left: inner, ..}=>self.leading_span(*inner),
+            _=>None,
+        }
+    }
+
+    /// Where the statement at `site` names the variable, as " on line N" so it can be added to the
+    /// end of a message. Empty if the name isn't written there.
+    pub fn site_line(&self, var: VarIndex, site: StmtIndex)->String {
+        match self.site_span(var, site) {
+            Some(span)=>format!(" on line {}", self.line_of(span.start)),
+            None=>String::new(),
         }
     }
 
+    /// Returns true if any of the diagnostics are errors
+    pub fn has_errors(&self)->bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /// Counts everything in the file. Patches are counted separately from the root statements and
+    /// expressions they belong to.
+    pub fn stats(&self)->FileStats {
+        FileStats {
+            stmts: self.stmts.len(),
+            patch_stmts: self.patch_stmts.values().map(Vec::len).sum(),
+            exprs: self.exprs.len(),
+            patch_exprs: self.patch_exprs.values().map(Vec::len).sum(),
+            scopes: self.scopes.len(),
+            functions: self.functions.len(),
+            vars: self.vars.len(),
+        }
+    }
+
+    /// A hash of the program that is the same every time the same source is converted, so a driver
+    /// can skip compiling a file that hasn't changed. Maps are hashed in key order instead of the
+    /// order they iterate in. The diagnostics, barriers, and argument order aren't hashed, since
+    /// they are found from the rest.
+    pub fn content_hash(&self, interner: &StringInterner)->u64 {
+        let mut hasher = FnvHasher::default();
+
+        // names and strings are indices into the interner, so what they point to is hashed too
+        for s in interner.strings.iter() {
+            s.hash(&mut hasher);
+        }
+
+        self.stmts.hash(&mut hasher);
+        sorted_entries(&self.patch_stmts, |root|*root).hash(&mut hasher);
+        self.exprs.hash(&mut hasher);
+        sorted_entries(&self.patch_exprs, |root|*root).hash(&mut hasher);
+        self.scopes.hash(&mut hasher);
+        self.functions.hash(&mut hasher);
+        self.vars.hash(&mut hasher);
+        sorted_entries(&self.constants, |name|name.0).hash(&mut hasher);
+        self.root_scope.hash(&mut hasher);
+        self.main.hash(&mut hasher);
+
+        return hasher.finish();
+    }
+
     pub fn add_var(&mut self, var: VarMetadata)->VarIndex {
         let index = VarIndex(self.vars.len());
         self.vars.push(var);
@@ -251,6 +862,96 @@ impl File {
         &self.scopes[scope.0]
     }
 
+    /// The number of parents between the scope and the root scope. Scopes that were merged away
+    /// have no parent, so they have a depth of 0 like the root.
+    pub fn scope_depth(&self, scope: ScopeIndex)->usize {
+        let mut depth = 0;
+        let mut current = self.get_scope(scope).parent;
+
+        while let Some(scope) = current {
+            depth += 1;
+            current = self.get_scope(scope).parent;
+        }
+
+        return depth;
+    }
+
+    /// Checks if `ancestor` is one of the parents of `descendant`. A scope is not its own ancestor.
+    pub fn is_ancestor(&self, ancestor: ScopeIndex, descendant: ScopeIndex)->bool {
+        let mut current = self.get_scope(descendant).parent;
+
+        while let Some(scope) = current {
+            if scope == ancestor {
+                return true;
+            }
+            current = self.get_scope(scope).parent;
+        }
+
+        return false;
+    }
+
+    /// Describes the constructs around the scope from the innermost out, like "in `if` block at
+    /// line 4, in function `f`". Returns `None` for the root scope.
+    pub fn scope_context(&self, scope: ScopeIndex, interner: &StringInterner)->Option<String> {
+        let mut parts = Vec::new();
+        let mut current = Some(scope);
+
+        while let Some(scope) = current {
+            let scope_ref = self.get_scope(scope);
+            if scope_ref.parent.is_none() {
+                break;
+            }
+
+            parts.push(self.describe_scope(scope, interner));
+            current = scope_ref.parent;
+        }
+
+        if parts.len() == 0 {
+            return None;
+        }
+
+        return Some(parts.join(", "));
+    }
+
+    /// Names the construct that owns the scope.
+    fn describe_scope(&self, scope: ScopeIndex, interner: &StringInterner)->String {
+        let function = self.functions.iter().find(|function|function.block.scope == scope);
+        if let Some(function) = function {
+            return format!("in function `{}`", interner.get_string(function.name));
+        }
+
+        for (_, stmt) in self.all_stmts() {
+            match stmt {
+                Stmt::IfElse{block, span, ..} if block.scope == scope=>{
+                    return format!("in `if` block at line {}", self.line_of(span.start));
+                },
+                Stmt::IfElse{else_block: Some(block), span, ..} if block.scope == scope=>{
+                    return format!("in `else` block at line {}", self.line_of(span.start));
+                },
+                Stmt::Conditional{actions, default, span, ..}=>{
+                    let is_arm = actions.iter()
+                        .chain(default)
+                        .any(|action|match action {
+                            ConditionalAction::Scope(block)=>block.scope == scope,
+                            ConditionalAction::Expr(_)=>false,
+                        });
+                    if is_arm {
+                        return format!("in `cond` arm at line {}", self.line_of(span.start));
+                    }
+                },
+                _=>{},
+            }
+        }
+
+        let scope_ref = self.get_scope(scope);
+        match (scope_ref.is_loop, scope_ref.label) {
+            (true, Some(label))=>format!("in loop `'{}`", interner.get_string(label)),
+            (true, None)=>"in a `loop`".to_string(),
+            (false, Some(label))=>format!("in scope `'{}`", interner.get_string(label)),
+            (false, None)=>"in a `scope` block".to_string(),
+        }
+    }
+
     pub fn get_mut_var(&mut self, var: VarIndex)->&mut VarMetadata {
         &mut self.vars[var.0]
     }
@@ -325,6 +1026,121 @@ impl File {
         }
     }
 
+    /// Yields the root statement followed by its patches.
+    pub fn stmt_with_patches(&self, root: usize)->impl Iterator<Item = (StmtIndex, &Stmt)> {
+        let patches = self.patch_stmts
+            .get(&root)
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(move |(i, patch)|(StmtIndex {root, patch: i + 1}, patch));
+
+        std::iter::once((StmtIndex {root, patch: 0}, &self.stmts[root])).chain(patches)
+    }
+
+    /// Iterates over every statement in the file, yielding each root followed by its patches.
+    pub fn all_stmts(&self)->impl Iterator<Item = (StmtIndex, &Stmt)> {
+        (0..self.stmts.len()).flat_map(move |root|self.stmt_with_patches(root))
+    }
+
+    /// Iterates over the statements directly in the scope in order, yielding each root followed by
+    /// its patches. Statements belonging to child scopes are skipped.
+    pub fn scope_stmts(&self, scope: ScopeIndex)->impl Iterator<Item = (StmtIndex, &Stmt)> {
+        let scope = self.get_scope(scope);
+        let (first, last) = (scope.first, scope.last);
+        let children = scope.scopes
+            .iter()
+            .map(|child|{
+                let child = self.get_scope(*child);
+                (child.first, child.last)
+            })
+            .collect::<Vec<_>>();
+
+        (first.root..last.root.wrapping_add(1))
+            .flat_map(move |root|self.stmt_with_patches(root))
+            .filter(move |(index, _)|index.within(first, last))
+            .filter(move |(index, _)|!children.iter().any(|(f, l)|index.within(*f, *l)))
+    }
+
+    /// Iterates over the statements of the block in order, yielding each root followed by its
+    /// patches. Statements of nested blocks are skipped, just like with `scope_stmts`.
+    pub fn block_stmts(&self, block: &Block)->impl Iterator<Item = (StmtIndex, &Stmt)> {
+        let (first, last) = (block.first, block.last);
+
+        self.scope_stmts(block.scope)
+            .filter(move |(index, _)|index.within(first, last))
+    }
+
+    /// Iterates over every expression in the file, yielding each root followed by its patches.
+    pub fn all_exprs(&self)->impl Iterator<Item = (ExprIndex, &Expr)> {
+        self.exprs
+            .iter()
+            .enumerate()
+            .flat_map(move |(root, expr)|{
+                let patches = self.patch_exprs
+                    .get(&root)
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                    .map(move |(i, patch)|(ExprIndex {root, patch: i + 1}, patch));
+
+                std::iter::once((ExprIndex {root, patch: 0}, expr)).chain(patches)
+            })
+    }
+
+    /// Returns every variable and function in the file in scope order. Within a scope, variables
+    /// come first in definition order, followed by functions in conversion order. Each one points
+    /// at where its name is written in its definition.
+    pub fn symbols<'a>(&self, interner: &StringInterner<'a>)->Vec<Symbol<'a>> {
+        let mut symbols = Vec::new();
+
+        for (i, scope) in self.scopes.iter().enumerate() {
+            let scope_index = ScopeIndex(i);
+
+            let mut vars = scope.vars
+                .values()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            vars.sort_by_key(|v|v.0);
+
+            for var in vars {
+                let meta = self.get_var(var);
+                symbols.push(Symbol {
+                    name: interner.get_string(meta.name),
+                    kind: SymbolKind::Var(var),
+                    scope: scope_index,
+                    span: meta.span.clone(),
+                });
+            }
+
+            let mut functions = scope.functions
+                .values()
+                .flat_map(|patterns|patterns.values())
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            functions.sort_by_key(|f|f.0);
+
+            for function in functions {
+                let def = self.get_function(function);
+                let kind = if def.is_proc {
+                    SymbolKind::Proc(function)
+                } else {
+                    SymbolKind::Func(function)
+                };
+                symbols.push(Symbol {
+                    name: interner.get_string(def.name),
+                    kind,
+                    scope: scope_index,
+                    span: def.span.clone(),
+                });
+            }
+        }
+
+        return symbols;
+    }
+
     pub fn get_mut_expr(&mut self, loc: ExprIndex)->&mut Expr {
         if loc.patch == 0 {
             &mut self.exprs[loc.root]
@@ -332,22 +1148,448 @@ impl File {
             &mut self.patch_exprs.get_mut(&loc.root).unwrap()[loc.patch - 1]
         }
     }
+
+    /// Prints the expression and everything it refers to. Variables and functions are shown by
+    /// name.
+    pub fn print_expr(&self, expr: ExprIndex, interner: &StringInterner) {
+        print!("{}", self.expr_to_string(expr, interner));
+    }
+
+    /// Renders the expression the same way as `print_expr`.
+    pub fn expr_to_string(&self, expr: ExprIndex, interner: &StringInterner)->String {
+        let mut out = String::new();
+        self.write_expr(&mut out, expr, interner);
+
+        return out;
+    }
+
+    fn write_expr(&self, out: &mut String, expr: ExprIndex, interner: &StringInterner) {
+        // parenthesize operands that aren't groups, like the parser's printer
+        let operand = |out: &mut String, expr: ExprIndex|{
+            if self.get_expr(expr).is_group() {
+                self.write_expr(out, expr, interner);
+            } else {
+                out.push('(');
+                self.write_expr(out, expr, interner);
+                out.push(')');
+            }
+        };
+
+        match self.get_expr(expr) {
+            Expr::Operation{left, right, op}=>{
+                operand(out, *left);
+                match op {
+                    Operator::Apply=>out.push(' '),
+                    op=>write!(out, " {} ", op.symbol()).unwrap(),
+                }
+                operand(out, *right);
+            },
+            Expr::Field{left, name}=>{
+                operand(out, *left);
+                write!(out, ".{}", interner.get_string(*name)).unwrap();
+            },
+            Expr::Cast{expr, ty}=>{
+                operand(out, *expr);
+                write!(out, " as {ty:?}").unwrap();
+            },
+            Expr::Group(items)=>{
+                out.push('(');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write_expr(out, *item, interner);
+                }
+                if items.len() == 1 {
+                    out.push(',');
+                }
+                out.push(')');
+            },
+            Expr::List(items)=>{
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write_expr(out, *item, interner);
+                }
+                out.push(']');
+            },
+            Expr::TupleIndex{left, index}=>{
+                operand(out, *left);
+                write!(out, ".{index}").unwrap();
+            },
+            Expr::RawVar(name)=>out.push_str(interner.get_string(*name)),
+            Expr::Number(n)=>write!(out, "{n}").unwrap(),
+            Expr::String(s)=>write!(out, "\"{}\"", interner.get_string(*s)).unwrap(),
+            Expr::Char(c)=>write!(out, "{c:?}").unwrap(),
+            Expr::Borrow(inner)=>{
+                out.push('&');
+                self.write_expr(out, *inner, interner);
+            },
+            Expr::BorrowMut(inner)=>{
+                out.push_str("&mut ");
+                self.write_expr(out, *inner, interner);
+            },
+            Expr::Deref(inner)=>{
+                out.push('*');
+                self.write_expr(out, *inner, interner);
+            },
+            Expr::Negate(inner)=>{
+                out.push('-');
+                self.write_expr(out, *inner, interner);
+            },
+            Expr::Heap(inner)=>{
+                out.push_str("heap ");
+                operand(out, *inner);
+            },
+            Expr::Stack(inner)=>{
+                out.push_str("stack ");
+                operand(out, *inner);
+            },
+            Expr::TypeOf(inner)=>{
+                out.push_str("typeof ");
+                operand(out, *inner);
+            },
+            Expr::None=>out.push_str("None"),
+            Expr::Var(var)=>out.push_str(interner.get_string(self.get_var(*var).name)),
+            Expr::Function(function)=>{
+                out.push_str(interner.get_string(self.get_function(*function).name));
+            },
+            Expr::Partial{function, args}=>{
+                out.push_str(interner.get_string(self.get_function(*function).name));
+                for arg in args {
+                    out.push(' ');
+                    operand(out, *arg);
+                }
+            },
+            Expr::Skip=>out.push_str("<skip>"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SymbolKind {
+    Var(VarIndex),
+    Func(FunctionIndex),
+    Proc(FunctionIndex),
 }
 
+/// A named item in the file. Used for tooling like outlines and "go to definition".
 #[derive(Debug)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub kind: SymbolKind,
+    /// The scope the symbol is defined in.
+    pub scope: ScopeIndex,
+    /// The byte range of the name in the symbol's definition.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Hash)]
 pub struct FunctionDef {
     /// This determines `func` or `proc` status.
     pub is_proc: bool,
     pub name: Name,
+    /// Where the name is written in the definition. Lambdas point at their operator instead.
+    pub span: Range<usize>,
     pub pattern: Rc<Pattern>,
+    /// The variables bound by the pattern, in pattern order.
+    pub params: Vec<VarIndex>,
+    /// The values given to the last parameters when a call leaves them out.
+    pub defaults: Vec<Expr>,
+    /// The type declared after `->`. Every `return` must match it.
+    pub return_type: Option<Type>,
+    /// The names of the returned tuple's elements, if the return type names them.
+    pub outputs: Vec<Name>,
+    /// The condition that must hold for this definition to be selected.
+    pub guard: Option<ExprIndex>,
+    /// The text of the `///` comments before the definition.
+    pub doc: Option<String>,
 
     pub block: Block,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Hash)]
 pub struct Block {
     pub first: StmtIndex,
     pub last: StmtIndex,
 
     pub scope: ScopeIndex,
 }
+
+
+/// The entries of the map sorted by `key`, so they are hashed in the same order every time.
+fn sorted_entries<K, V, S, O: Ord>(map: &HashMap<K, V, S>, key: impl Fn(&K)->O)->Vec<(&K, &V)> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(k, _)|key(k));
+
+    return entries;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_debug_with_their_kind() {
+        assert_eq!(format!("{:?}", StmtIndex{root: 3, patch: 0}), "Stmt#3");
+        assert_eq!(format!("{:?}", ExprIndex{root: 3, patch: 2}), "Expr#3.2");
+        assert_eq!(format!("{:?}", ScopeIndex(0)), "Scope#0");
+        assert_eq!(format!("{:?}", FunctionIndex(1)), "Function#1");
+        assert_eq!(format!("{:?}", VarIndex(7)), "Var#7");
+
+        assert_eq!(format!("{:?}", StmtIndex::invalid()), "<invalid>");
+        assert_eq!(format!("{:?}", VarIndex::invalid()), "<invalid>");
+    }
+
+    #[test]
+    fn parse_runs_the_whole_pipeline() {
+        let (file, interner) = File::parse("func double x\n    x * 2\nlet y = double 4\n").unwrap();
+
+        assert_eq!(file.functions.len(), 1);
+        assert_eq!(interner.get_string(file.functions[0].name), "double");
+        assert!(!file.has_errors());
+
+        assert!(matches!(File::parse("return 1\n"), Err(ProgramError::Diagnostics(_))));
+        assert!(matches!(File::parse("let = 1\n"), Err(ProgramError::Parse(_))));
+    }
+
+    #[test]
+    fn scope_stmts_skip_child_scopes() {
+        let source = concat!(
+            "let a = 1\n",
+            "scope 'outer\n",
+            "    let b = 2\n",
+            "    scope 'inner\n",
+            "        let c = 3\n",
+            "    let d = 4\n",
+            "let e = 5\n",
+        );
+        let (file, interner) = File::parse(source).unwrap();
+        let defined = |scope: ScopeIndex|{
+            file.scope_stmts(scope)
+                .filter_map(|(_, stmt)|match stmt {
+                    Stmt::VarDef(var)=>Some(interner.get_string(file.get_var(*var).name)),
+                    _=>None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let outer = file.get_scope(file.root_scope).scopes[0];
+        let inner = file.get_scope(outer).scopes[0];
+        assert_eq!(defined(file.root_scope), ["a", "e"]);
+        assert_eq!(defined(outer), ["b", "d"]);
+        assert_eq!(defined(inner), ["c"]);
+    }
+
+    #[test]
+    fn scope_depth_and_ancestry() {
+        let source = concat!(
+            "scope 'outer\n",
+            "    let b = 2\n",
+            "    scope 'inner\n",
+            "        let c = 3\n",
+            "    let d = b\n",
+        );
+        let (file, _) = File::parse(source).unwrap();
+        let root = file.root_scope;
+        let outer = file.get_scope(root).scopes[0];
+        let inner = file.get_scope(outer).scopes[0];
+
+        assert_eq!(file.scope_depth(root), 0);
+        assert_eq!(file.scope_depth(outer), 1);
+        assert_eq!(file.scope_depth(inner), 2);
+
+        assert!(file.is_ancestor(root, inner));
+        assert!(file.is_ancestor(outer, inner));
+        assert!(!file.is_ancestor(inner, outer));
+        assert!(!file.is_ancestor(outer, outer));
+    }
+
+    #[test]
+    fn terminators() {
+        let expr = ExprIndex{root: 0, patch: 0};
+        let stmt = StmtIndex{root: 0, patch: 0};
+        let block = Block {
+            first: stmt,
+            last: stmt,
+            scope: ScopeIndex(0),
+        };
+        let name = crate::Index(0);
+
+        let terminators = [
+            Stmt::Return{function: FunctionIndex(0), data: None},
+            Stmt::Return{function: FunctionIndex(0), data: Some(expr)},
+            Stmt::JumpTo(stmt),
+        ];
+        let others = [
+            Stmt::VarDef(VarIndex(0)),
+            Stmt::Destructure {
+                pattern: Rc::new(Pattern::Group(Vec::new())),
+                data: expr,
+                vars: Vec::new(),
+            },
+            Stmt::VarSet{name, span: None, data: expr, var: VarIndex(0)},
+            Stmt::VarSetMulti{names: Vec::new(), spans: Vec::new(), data: expr, vars: Vec::new()},
+            Stmt::IfElse {
+                condition: expr,
+                block,
+                else_block: None,
+                last: stmt,
+                span: 0..0,
+            },
+            Stmt::Conditional {
+                conditions: Vec::new(),
+                actions: Vec::new(),
+                default: None,
+                last: stmt,
+                span: 0..0,
+            },
+            Stmt::Disown(expr),
+            Stmt::Expr(expr),
+            Stmt::Skip,
+        ];
+
+        assert!(terminators.iter().all(Stmt::is_terminator));
+        assert!(!others.iter().any(Stmt::is_terminator));
+    }
+
+    #[test]
+    fn block_stmts_skip_nested_blocks() {
+        let source = concat!(
+            "func f x\n",
+            "    if x > 1\n",
+            "        print 1\n",
+            "        if x > 2\n",
+            "            print 2\n",
+            "        print 3\n",
+            "    x\n",
+        );
+        let (file, interner) = File::parse(source).unwrap();
+
+        // blocks are converted before the statement they belong to, so the outer `if` is last
+        let block = file.all_stmts()
+            .filter_map(|(_, stmt)|match stmt {
+                Stmt::IfElse{block, ..}=>Some(block),
+                _=>None,
+            })
+            .last()
+            .unwrap();
+        let stmts = file.block_stmts(block)
+            .map(|(_, stmt)|match stmt {
+                Stmt::Expr(expr)=>file.expr_to_string(*expr, &interner),
+                Stmt::IfElse{..}=>"if".to_string(),
+                stmt=>panic!("unexpected statement {stmt:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(stmts, ["print 1", "if", "print 3"]);
+    }
+
+    #[test]
+    fn stats_count_the_items() {
+        let (file, _) = File::parse("let a = 1\nfunc f x\n    x + a\nprint (f 2)\n").unwrap();
+
+        // the `let`, the `return` of `f`, and the `print`. Each of the 9 expressions is a name,
+        // a number, or an operation on them.
+        assert_eq!(file.stats(), FileStats {
+            stmts: 3,
+            patch_stmts: 0,
+            exprs: 9,
+            patch_exprs: 0,
+            scopes: 2,
+            functions: 1,
+            vars: 2,
+        });
+    }
+
+    #[test]
+    fn symbols_have_kinds_and_spans() {
+        let source = "let x = 1\nfunc f (a)\n    a\nproc p\n    print x\n";
+        let (file, interner) = File::parse(source).unwrap();
+
+        let symbols = file.symbols(&interner)
+            .into_iter()
+            .map(|symbol|{
+                let kind = match symbol.kind {
+                    SymbolKind::Var(_)=>"var",
+                    SymbolKind::Func(_)=>"func",
+                    SymbolKind::Proc(_)=>"proc",
+                };
+                (symbol.name, kind, &source[symbol.span])
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(symbols, [
+            ("x", "var", "x"),
+            ("f", "func", "f"),
+            ("p", "proc", "p"),
+            ("a", "var", "a"),
+        ]);
+    }
+
+    #[test]
+    fn scope_context_names_the_enclosing_constructs() {
+        let source = concat!(
+            "func outer a\n",
+            "    func inner b\n",
+            "        if b > 1\n",
+            "            let x = stack 5\n",
+            "            return &x\n",
+            "        0\n",
+            "    inner a\n",
+        );
+        let Err(ProgramError::Diagnostics(diagnostics)) = File::parse(source) else {
+            panic!("returning a borrow of a stack pinned variable should be an error");
+        };
+
+        assert!(diagnostics.iter().any(|d|d.msg.ends_with(
+            "(in `if` block at line 3, in function `inner`, in function `outer`)",
+        )));
+    }
+
+    #[test]
+    fn content_hashes_follow_the_source() {
+        let hash = |source: &str|{
+            let (file, interner) = File::parse(source).unwrap();
+            file.content_hash(&interner)
+        };
+        let func = "func f x\n    let y = x * 2\n    y\n";
+        let source = format!("{func}let a = f 1\nlet b = a + 2\n");
+
+        assert_eq!(hash(&source), hash(&source));
+        assert_ne!(hash(&source), hash(&format!("{func}let a = f 1\nlet b = a + 3\n")));
+        // only a name changed
+        assert_ne!(hash(&source), hash(&format!("{func}let a = f 1\nlet c = a + 2\n")));
+    }
+
+    #[test]
+    fn live_ranges_overlap_only_when_they_share_statements() {
+        let source = concat!(
+            "func f x\n",
+            "    let a = x + 1\n",
+            "    let b = a * 2\n",
+            "    let c = x + 3\n",
+            "    let d = c * 4\n",
+            "    (b, d)\n",
+        );
+        let (file, interner) = File::parse(source).unwrap();
+        let var = |name: &str|{
+            file.vars.iter()
+                .find(|var|interner.get_string(var.name) == name)
+                .unwrap()
+        };
+
+        let (a_first, a_last) = var("a").liveness();
+        let (c_first, _) = var("c").liveness();
+        assert!(a_first < a_last && a_last < c_first);
+        assert!(!var("a").live_overlaps(var("c")));
+        assert!(!var("c").live_overlaps(var("a")));
+
+        // `d` is defined where `c` is last used, and `b` is used after both
+        assert!(var("c").live_overlaps(var("d")));
+        assert!(var("b").live_overlaps(var("c")));
+    }
+}
@@ -0,0 +1,105 @@
+// consistency checks for the lowered tree
+
+
+use super::tree::*;
+
+
+/// Checks that every `JumpTo` lands on a statement in the file, or just past the last one, and
+/// doesn't skip the definition of a variable that is still in scope where it lands. A bad jump is
+/// a bug in conversion or lowering, so these are reported as internal compiler errors.
+///
+/// Nothing is checked if there are already errors, because a `break` without a target is left
+/// pointing nowhere after its error is reported.
+pub fn verify_jumps(file: &mut File) {
+    if file.has_errors() {
+        return;
+    }
+
+    let mut diagnostics = Vec::new();
+
+    for (index, stmt) in file.all_stmts() {
+        let Stmt::JumpTo(target) = *stmt else {continue};
+
+        if !in_range(file, target) {
+            diagnostics.push(Diagnostic::internal(format!(
+                "{index:?} jumps to {target:?}, which is out of range",
+            )));
+            continue;
+        }
+
+        // only forward jumps can skip a definition
+        let skipped = file.vars
+            .iter()
+            .filter(|var|index < var.definition && var.definition < target)
+            .find(|var|scope_contains(file, var.in_scope, target));
+        if let Some(var) = skipped {
+            diagnostics.push(Diagnostic::internal(format!(
+                "{index:?} jumps to {target:?}, skipping the definition at {:?}",
+                var.definition,
+            )));
+        }
+    }
+
+    file.diagnostics.append(&mut diagnostics);
+}
+
+/// Jumping to the index right after the last statement ends the file.
+fn in_range(file: &File, target: StmtIndex)->bool {
+    if target.patch == 0 {
+        return target.root <= file.stmts.len();
+    }
+
+    file.patch_stmts
+        .get(&target.root)
+        .map(|patches|target.patch <= patches.len())
+        .unwrap_or(false)
+}
+
+fn scope_contains(file: &File, scope: ScopeIndex, stmt: StmtIndex)->bool {
+    let scope = file.get_scope(scope);
+    stmt.within(scope.first, scope.last)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use super::{
+        *,
+        super::{
+            conversion::convert_parse_tree,
+            lowering::lower_conditionals,
+        },
+    };
+
+    #[test]
+    fn corrupted_jumps_are_internal_errors() {
+        let source = concat!(
+            "let a = 1\n",
+            "scope 'out\n",
+            "    cond\n",
+            "        a > 1 => scope\n",
+            "            break 'out\n",
+            "        else => print a\n",
+            "    print 2\n",
+            "let d = a\n",
+        );
+        let (stmts, interner, _) = Parser::new(source).parse_all().unwrap();
+        let mut file = convert_parse_tree(stmts, source, &interner, &[]);
+        lower_conditionals(&mut file);
+
+        verify_jumps(&mut file);
+        assert!(!file.has_errors(), "{:?}", file.diagnostics);
+
+        let jump = file.all_stmts()
+            .find(|(_, stmt)|matches!(stmt, Stmt::JumpTo(_)))
+            .map(|(index, _)|index)
+            .unwrap();
+        *file.get_mut_stmt(jump) = Stmt::JumpTo(StmtIndex{root: 1000, patch: 0});
+
+        verify_jumps(&mut file);
+        assert_eq!(file.diagnostics.len(), 1);
+        assert!(file.diagnostics[0].msg.starts_with("Internal compiler error"));
+        assert!(file.diagnostics[0].msg.ends_with("jumps to Stmt#1000, which is out of range"));
+    }
+}
@@ -0,0 +1,202 @@
+// reparsing single statements after an edit
+
+
+use logos::Logos;
+use std::ops::Range;
+use crate::{
+    lexer::Token,
+    StringInterner,
+};
+use super::{
+    OperatorTable,
+    ParseResult,
+    Parser,
+    Stmt,
+};
+
+
+/// A parsed file that remembers where each top-level statement came from, so an edit only has to
+/// reparse the statement it is in.
+///
+/// The interner borrows from every version of the source that was parsed, so they all have to
+/// outlive the file.
+pub struct ParsedFile<'a> {
+    pub source: &'a str,
+    pub stmts: Vec<Stmt>,
+    /// The byte range of each statement in `stmts`. The ranges cover the whole source with no gaps,
+    /// so blank lines and comments belong to the statement before them.
+    pub spans: Vec<Range<usize>>,
+    pub interner: StringInterner<'a>,
+    /// Every operator declared in the file, so a statement can use ones declared after it.
+    pub operators: OperatorTable<'a>,
+}
+impl<'a> ParsedFile<'a> {
+    pub fn parse(source: &'a str)->ParseResult<Self> {
+        let mut parser = Parser::new(source);
+        let (stmts, mut spans) = parser.parse_file_spanned()?;
+
+        // trailing comments don't make tokens, so they aren't in the last statement yet
+        if let Some(last) = spans.last_mut() {
+            last.end = source.len();
+        }
+
+        return Ok(ParsedFile {
+            source,
+            stmts,
+            spans,
+            operators: parser.operators().clone(),
+            interner: parser.into_interner(),
+        });
+    }
+
+    /// Updates the file after the bytes in `edit` of the old source were replaced, giving the new
+    /// `source`. Only the statement containing the edit is parsed again, unless the edit spans more
+    /// than one statement or changes where the statements start and end. Then the whole file is
+    /// parsed again. The file is left unchanged if the new source has a parse error.
+    pub fn reparse(&mut self, source: &'a str, edit: Range<usize>)->ParseResult<()> {
+        let inserted = (source.len() + edit.len()).checked_sub(self.source.len());
+
+        let index = self.spans
+            .iter()
+            .position(|span|edit.start < span.end)
+            .or(self.spans.len().checked_sub(1));
+
+        let (index, inserted) = match (index, inserted) {
+            (Some(index), Some(inserted)) if edit.end <= self.spans[index].end=>(index, inserted),
+            _=>return self.reparse_all(source),
+        };
+
+        // changing an operator declaration changes how every other statement parses
+        if is_infix(&self.stmts[index]) {
+            return self.reparse_all(source);
+        }
+
+        let start = self.spans[index].start;
+        let end = self.spans[index].end + inserted - edit.len();
+
+        if !ends_at_token(source, start, end) {
+            return self.reparse_all(source);
+        }
+
+        // statements are lexed in place so the spans inside of them are still for the whole source
+        let mut lexer = Token::lexer(&source[..end]);
+        lexer.bump(start);
+
+        let interner = std::mem::replace(&mut self.interner, StringInterner::new());
+        let mut parser = Parser::from_lexer(source, lexer, interner, self.operators.clone());
+        let stmts = parser.parse_file();
+        self.interner = parser.into_interner();
+
+        let stmt = match stmts {
+            Ok(mut stmts) if stmts.len() == 1 && !is_infix(&stmts[0])=>stmts.pop().unwrap(),
+            // the edit moved a statement boundary, or the error could be caused by the statements
+            // around it
+            _=>return self.reparse_all(source),
+        };
+
+        self.stmts[index] = stmt;
+        self.spans[index].end = end;
+        for span in &mut self.spans[index + 1..] {
+            span.start = span.start + inserted - edit.len();
+            span.end = span.end + inserted - edit.len();
+        }
+        self.source = source;
+
+        return Ok(());
+    }
+
+    fn reparse_all(&mut self, source: &'a str)->ParseResult<()> {
+        *self = Self::parse(source)?;
+
+        return Ok(());
+    }
+}
+
+fn is_infix(stmt: &Stmt)->bool {
+    match stmt {
+        Stmt::FunctionDef{infix, ..}=>infix.is_some(),
+        Stmt::Cfg{stmt, ..}=>is_infix(stmt),
+        _=>false,
+    }
+}
+
+/// Checks that no token runs past `end`. A line continuation or a comma at the end of a statement
+/// takes the line break with it, joining the statement to the next one.
+fn ends_at_token(source: &str, start: usize, end: usize)->bool {
+    let mut lexer = Token::lexer(source);
+    lexer.bump(start);
+
+    while lexer.next().is_some() {
+        let token_end = lexer.span().end;
+        if token_end >= end {
+            return token_end == end;
+        }
+    }
+
+    return true;
+}
+
+impl<'a> Parser<'a> {
+    /// Parses the whole file like `parse_file`, and also returns the byte range of each statement.
+    /// Each range starts where the last one ended, and ends after the statement's last token.
+    pub fn parse_file_spanned(&mut self)->ParseResult<(Vec<Stmt>, Vec<Range<usize>>)> {
+        let mut stmts = Vec::new();
+        let mut spans = Vec::new();
+        let mut start = 0;
+        self.check_lex_errors()?;
+        self.ws_stack.push(0);
+
+        while self.peek(0) != Token::EOF {
+            if let Token::Whitespace(_) = self.peek(0) {
+                self.skip_misindented(0);
+                continue;
+            }
+
+            let stmt = self.parse_stmt()?;
+            let end = self.inner.span().end;
+
+            // the bindings of a `where` clause get empty spans, so an edit to them lands in the
+            // statement's span and parses them again with it
+            for binding in self.where_bindings.drain(..) {
+                stmts.push(binding);
+                spans.push(start..start);
+            }
+            stmts.push(stmt);
+            spans.push(start..end);
+            start = end;
+        }
+        self.check_recovered()?;
+
+        return Ok((stmts, spans));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Expr;
+
+    fn number(stmt: &Stmt)->i64 {
+        match stmt {
+            Stmt::VarDef{data: Some(Expr::Number(n, _)), ..}=>*n,
+            _=>panic!("expected a `let` of a number, found {stmt:?}"),
+        }
+    }
+
+    #[test]
+    fn edits_reparse_one_statement() {
+        let mut file = ParsedFile::parse("let a = 1\nlet b = 2\nlet c = 3\n").unwrap();
+        assert_eq!(file.spans, [0..10, 10..20, 20..30]);
+
+        let edited = "let a = 1\nlet b = 20\nlet c = 3\n";
+        file.reparse(edited, 18..19).unwrap();
+        assert_eq!(file.stmts.iter().map(number).collect::<Vec<_>>(), [1, 20, 3]);
+        assert_eq!(file.spans, [0..10, 10..21, 21..31]);
+
+        // a new line makes a new statement, so the whole file is parsed again
+        let split = "let a = 1\nlet b = 20\nlet d = 4\nlet c = 3\n";
+        file.reparse(split, 21..21).unwrap();
+        assert_eq!(file.stmts.iter().map(number).collect::<Vec<_>>(), [1, 20, 4, 3]);
+    }
+}
@@ -5,7 +5,11 @@ use parser_helper::{
 };
 use misc_utils::stack::Stack;
 use logos::Logos;
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    num::IntErrorKind,
+    ops::Range,
+};
 use crate::{
     lexer::*,
     StringInterner,
@@ -14,31 +18,168 @@ use crate::{
 use Keyword::*;
 
 pub use tree::*;
+pub use operators::*;
+pub use incremental::*;
 
 
 mod tree;
+mod operators;
+mod incremental;
 
 
 pub type ParseResult<T> = Result<T, SimpleError<Cow<'static, str>>>;
 
 
+/// The left binding power of `as`. Casts bind looser than arithmetic and tighter than `and`/`or`,
+/// so `a + b as Number` casts the sum.
+const CAST_PREC: u8 = 2;
+
+/// The left binding power of an `infix` operator declared without one, the same as `+`.
+const DEFAULT_INFIX_PREC: u8 = 6;
+
+
 pub struct Parser<'a> {
     pub interner: StringInterner<'a>,
 
-    inner: LookaheadLexer<2, Token<'a>, LogosWrapper<'a, Token<'a>>, ()>,
+    inner: LookaheadLexer<3, Token<'a>, LogosWrapper<'a, Token<'a>>, ()>,
     ws_stack: Stack<usize>,
+    operators: OperatorTable<'a>,
+    source: &'a str,
+    /// The byte ranges the lexer couldn't make a token from. The lexer skips them, so they are
+    /// found before parsing.
+    lex_errors: Vec<Range<usize>>,
+    /// The `let`s from the `where` clause of the statement that was just parsed. They go before it.
+    where_bindings: Vec<Stmt>,
+    /// Errors that parsing continued after, by skipping the lines that caused them.
+    recovered: Vec<SimpleError<Cow<'static, str>>>,
+    /// Style warnings, like parentheses that don't change how their expression parses.
+    warnings: Vec<SimpleError<Cow<'static, str>>>,
+    /// The binding power of the outermost operator of the last expression `parse_expr` returned,
+    /// or `None` if it wasn't an operation. Prefix operators have no left side, so their left
+    /// binding power is `u8::MAX`.
+    last_prec: Option<(u8, u8)>,
+    /// Statements that `parse_next_stmt` parsed but hasn't returned yet. The last one is next.
+    queued: Vec<Stmt>,
+    /// Whether `parse_next_stmt` has been called yet.
+    started: bool,
+    /// Whether parentheses around a single expression are kept as `Expr::Paren`.
+    preserve_parens: bool,
 }
 impl<'a> Parser<'a> {
+    #[inline]
     pub fn new(source: &'a str)->Self {
-        let l = LookaheadLexer::new(LogosWrapper(Token::lexer(source)), ());
+        Self::with_operators(source, OperatorTable::default())
+    }
+
+    /// Creates a parser that uses the given infix operators instead of the language's own.
+    pub fn with_operators(source: &'a str, operators: OperatorTable<'a>)->Self {
+        Self::from_lexer(source, Token::lexer(source), StringInterner::new(), operators)
+    }
+
+    /// Creates a parser that starts wherever the lexer is, so the spans it reports are still for
+    /// the whole `source`.
+    fn from_lexer(
+        source: &'a str,
+        lexer: logos::Lexer<'a, Token<'a>>,
+        interner: StringInterner<'a>,
+        operators: OperatorTable<'a>,
+    )->Self {
+        let lex_errors = find_lex_errors(lexer.clone());
+        let l = LookaheadLexer::new(LogosWrapper(lexer), ());
 
         return Parser{
             inner: l,
-            interner: StringInterner::new(),
+            interner,
             ws_stack: Stack::new(),
+            operators,
+            source,
+            lex_errors,
+            where_bindings: Vec::new(),
+            recovered: Vec::new(),
+            warnings: Vec::new(),
+            last_prec: None,
+            queued: Vec::new(),
+            started: false,
+            preserve_parens: false,
         };
     }
 
+    /// Keeps the parentheses written around single expressions in the tree as `Expr::Paren`, even
+    /// when they don't change how it parses. Conversion ignores them either way.
+    pub fn preserve_parens(mut self)->Self {
+        self.preserve_parens = true;
+        self
+    }
+
+    /// An error for each character that doesn't start a token, in the order they appear.
+    pub fn lex_errors(&self)->impl Iterator<Item = SimpleError<Cow<'static, str>>> + '_ {
+        self.lex_errors.iter().map(|span|SimpleError {
+            msg: format!("Unexpected character `{}`", &self.source[span.clone()]).into(),
+            span: span.clone(),
+        })
+    }
+
+    fn check_lex_errors(&self)->ParseResult<()> {
+        match self.lex_errors().next() {
+            Some(error)=>Err(error),
+            None=>Ok(()),
+        }
+    }
+
+    /// Returns the first error that parsing recovered from, if there are any.
+    fn check_recovered(&mut self)->ParseResult<()> {
+        match self.recovered.len() {
+            0=>Ok(()),
+            _=>Err(self.recovered.remove(0)),
+        }
+    }
+
+    /// Records an error for a line that is indented deeper than the block it is in. The line is
+    /// skipped, along with any lines after it that are also deeper, so parsing picks back up at the
+    /// block's next line.
+    fn skip_misindented(&mut self, indent: usize) {
+        self.next();
+        self.recovered.push(self.inner.error("Unexpected indent".into()));
+
+        loop {
+            match self.peek(0) {
+                Token::EOF=>break,
+                Token::Newline=>{
+                    self.next();
+                    match self.peek(0) {
+                        Token::Whitespace(amt) if amt > indent=>{},
+                        Token::Newline=>{},
+                        _=>break,
+                    }
+                },
+                _=>{
+                    self.next();
+                },
+            }
+        }
+    }
+
+    /// Skips to the start of the next statement after an error. Everything up to the next newline
+    /// or `;` outside of brackets is skipped, along with the newline or `;` and any blank lines
+    /// after it. Newlines inside of brackets don't end the statement.
+    pub fn recover_to_statement_boundary(&mut self) {
+        let mut depth = 0usize;
+
+        loop {
+            match self.peek(0) {
+                Token::EOF=>return,
+                Token::Newline|Token::Semicolon if depth == 0=>break,
+                Token::ParenStart|Token::SquareStart|Token::CurlyStart=>depth += 1,
+                Token::ParenEnd|Token::SquareEnd|Token::CurlyEnd=>depth = depth.saturating_sub(1),
+                _=>{},
+            }
+            self.next();
+        }
+
+        self.next();
+        self.skip_nl();
+    }
+
     fn indent(&mut self)->ParseResult<usize> {
         match self.next() {
             Token::Whitespace(count)=>Ok(count),
@@ -60,6 +201,19 @@ impl<'a> Parser<'a> {
         return ret;
     }
 
+    /// Checks that a line dedented out of the block at `indent` lines up with `parent`, the
+    /// indentation of the block around it, or with a block further out. The blocks further out
+    /// check it again when they end.
+    fn check_dedent(&mut self, indent: usize, parent: usize)->ParseResult<()> {
+        match self.peek(0) {
+            Token::Whitespace(amt) if amt < indent && amt > parent=>{
+                self.next();
+                self.error("Unindent does not match any outer indentation level")
+            },
+            _=>Ok(()),
+        }
+    }
+
     fn error<T, S: Into<Cow<'static, str>>>(&self, msg: S)->ParseResult<T> {
         Err(self.inner.error(msg.into()))
     }
@@ -84,6 +238,13 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Skips whitespace and newlines. Only for inside of brackets, where lines don't matter.
+    fn skip_ws_nl(&mut self) {
+        while let Token::Whitespace(_)|Token::Newline = self.peek(0) {
+            self.next();
+        }
+    }
+
     fn ws(&mut self)->ParseResult<()> {
         match self.next() {
             Token::Whitespace(_)=>{
@@ -143,23 +304,235 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// A type name, or a list type like `[Number]`. List types are interned as they are written,
+    /// and resolved along with the other type names.
+    fn type_name(&mut self)->ParseResult<Index> {
+        if !self.try_match(Token::SquareStart) {
+            return self.word();
+        }
+
+        let start = self.inner.span().start;
+        self.skip_ws();
+        self.type_name()?;
+        self.skip_ws();
+        self.match_token(Token::SquareEnd)?;
+        let end = self.inner.span().end;
+
+        return Ok(self.intern(&self.source[start..end]));
+    }
+
+    /// Warns about the parentheses at `span` if they are still in `paren` and the operator after
+    /// them, with the left binding power `next`, wouldn't take the right side of the expression in
+    /// them without the parentheses. `None` means nothing comes after them.
+    fn check_paren(
+        &mut self,
+        paren: &mut Option<(Option<(u8, u8)>, Range<usize>)>,
+        next: Option<u8>,
+    ) {
+        let (inner, span) = match paren.take() {
+            Some(paren)=>paren,
+            None=>return,
+        };
+
+        let needed = match (inner, next) {
+            (Some((_, inner_r)), Some(next_l))=>next_l >= inner_r,
+            _=>false,
+        };
+        if !needed {
+            self.warnings.push(SimpleError {
+                msg: "Redundant parentheses".into(),
+                span,
+            });
+        }
+    }
+
+    /// Interns and returns the label if the next token is one.
+    fn try_label(&mut self)->Option<Index> {
+        match self.peek(0) {
+            Token::Label(l)=>{
+                self.next();
+                Some(self.intern(l))
+            },
+            _=>None,
+        }
+    }
+
     fn parse_num(&mut self, num_str: &'a str)->ParseResult<i64> {
-        if let Ok(num) = num_str.parse::<i64>() {
-            Ok(num)
-        } else {
-            self.error("Error parsing number")
+        let (radix, digits) = Radix::split(num_str);
+
+        // the lexer only allows underscores between digits
+        match i64::from_str_radix(&digits.replace('_', ""), radix.base()) {
+            Ok(num)=>Ok(num),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow=>{
+                self.error("Integer literal out of range for i64")
+            },
+            Err(_)=>self.error("Error parsing number"),
+        }
+    }
+
+    /// Parses the contents of a char literal, including the quotes.
+    fn parse_char(&mut self, char_str: &'a str)->ParseResult<char> {
+        let inner = &char_str[1..char_str.len() - 1];
+
+        let escape = match (inner.strip_prefix('\\'), inner.chars().next()) {
+            (Some(escape), _)=>escape,
+            (None, Some(c))=>return Ok(c),
+            (None, None)=>return self.error("Empty character literal"),
+        };
+
+        match escape {
+            "n"=>return Ok('\n'),
+            "r"=>return Ok('\r'),
+            "t"=>return Ok('\t'),
+            "0"=>return Ok('\0'),
+            "\\"=>return Ok('\\'),
+            "'"=>return Ok('\''),
+            "\""=>return Ok('"'),
+            _=>{},
+        }
+
+        if let Some(hex) = escape.strip_prefix('x') {
+            if hex.len() != 2 {
+                return self.error("Hex escapes must have exactly 2 digits");
+            }
+            return match u8::from_str_radix(hex, 16) {
+                Ok(byte) if byte <= 0x7F=>Ok(byte as char),
+                _=>self.error("Hex escapes must be in the ASCII range `\\x00`-`\\x7F`"),
+            };
         }
+
+        if let Some(code) = escape.strip_prefix("u{").and_then(|e|e.strip_suffix('}')) {
+            if code.len() == 0 || code.len() > 6 {
+                return self.error("Unicode escapes must have 1 to 6 hex digits");
+            }
+            return match u32::from_str_radix(code, 16).ok().and_then(char::from_u32) {
+                Some(c)=>Ok(c),
+                None=>self.error("Unicode escape is not a valid unicode scalar value"),
+            };
+        }
+
+        return self.error("Unknown character escape");
     }
 
+    /// Parses the whole file. If there are any errors, only the first one is returned.
     pub fn parse_file(&mut self)->ParseResult<Vec<Stmt>> {
+        let stmts = self.parse_stmts();
+        self.check_recovered()?;
+
+        return Ok(stmts);
+    }
+
+    /// Parses every statement in the file. The errors are left in `recovered`.
+    fn parse_stmts(&mut self)->Vec<Stmt> {
         let mut stmts = Vec::new();
-        self.ws_stack.push(0);
+        let mut errors = Vec::new();
 
-        while self.peek(0) != Token::EOF {
-            stmts.push(self.parse_stmt()?);
+        while let Some(result) = self.parse_next_stmt() {
+            match result {
+                Ok(stmt)=>stmts.push(stmt),
+                Err(error)=>errors.push(error),
+            }
         }
 
-        return Ok(stmts);
+        self.recovered = errors;
+
+        return stmts;
+    }
+
+    /// Parses the next top-level statement, or returns `None` at the end of the file, so a large
+    /// file can be handled one statement at a time. The `let`s of a `where` clause are returned
+    /// before their statement, like in `parse_file`.
+    ///
+    /// Errors are returned in the order they are found. Parsing continues after them the same way
+    /// `parse_all` does, except for characters the lexer couldn't make a token from, which end the
+    /// file.
+    pub fn parse_next_stmt(&mut self)->Option<ParseResult<Stmt>> {
+        if !self.started {
+            self.started = true;
+            self.ws_stack.push(0);
+            if let Err(error) = self.check_lex_errors() {
+                return Some(Err(error));
+            }
+        } else if self.lex_errors.len() > 0 {
+            return None;
+        }
+
+        if self.recovered.len() > 0 {
+            return Some(Err(self.recovered.remove(0)));
+        }
+        if let Some(stmt) = self.queued.pop() {
+            return Some(Ok(stmt));
+        }
+
+        if self.peek(0) == Token::EOF {
+            return None;
+        }
+        if let Token::Whitespace(_) = self.peek(0) {
+            self.skip_misindented(0);
+            return self.parse_next_stmt();
+        }
+
+        match self.parse_stmt() {
+            Ok(stmt)=>{
+                self.queued.push(stmt);
+                self.queued.extend(self.where_bindings.drain(..).rev());
+            },
+            Err(error)=>{
+                self.recovered.push(error);
+                self.where_bindings.clear();
+                self.recover_to_statement_boundary();
+
+                // a block that failed to parse leaves its indentation behind, and the rest of its
+                // lines would only be reported as misindented
+                while *self.ws_stack.last() != 0 {
+                    self.ws_stack.pop();
+                }
+                while let Token::Whitespace(_) = self.peek(0) {
+                    self.recover_to_statement_boundary();
+                }
+            },
+        }
+
+        return self.parse_next_stmt();
+    }
+
+    /// Parses the whole file and consumes the parser, returning the statements and the interner
+    /// their names resolve in.
+    pub fn parse(mut self)->ParseResult<(Vec<Stmt>, StringInterner<'a>)> {
+        let stmts = self.parse_file()?;
+
+        return Ok((stmts, self.into_interner()));
+    }
+
+    /// Like `parse`, but returns every error instead of only the first. Parsing continues after a
+    /// misindented line or a top-level statement that fails to parse, so there can be more than
+    /// one. The style warnings are returned with the statements.
+    pub fn parse_all(
+        mut self,
+    )->Result<
+        (Vec<Stmt>, StringInterner<'a>, Vec<SimpleError<Cow<'static, str>>>),
+        Vec<SimpleError<Cow<'static, str>>>,
+    > {
+        let stmts = self.parse_stmts();
+        let errors = std::mem::take(&mut self.recovered);
+
+        if errors.len() > 0 {
+            return Err(errors);
+        }
+
+        let warnings = std::mem::take(&mut self.warnings);
+        return Ok((stmts, self.into_interner(), warnings));
+    }
+
+    #[inline]
+    pub fn into_interner(self)->StringInterner<'a> {
+        self.interner
+    }
+
+    /// The operators the parser knows, including the ones declared with `infix` so far.
+    #[inline]
+    pub fn operators(&self)->&OperatorTable<'a> {
+        &self.operators
     }
 
     pub fn parse_stmt(&mut self)->ParseResult<Stmt> {
@@ -167,43 +540,121 @@ impl<'a> Parser<'a> {
         match self.peek(0) {
             Token::Keyword(Set)=>self.parse_var_set(),
             Token::Keyword(Let)=>self.parse_var_def(),
-            Token::Keyword(Proc|Func)=>self.parse_function(),
+            Token::Keyword(Const)=>self.parse_const(),
+            Token::Keyword(Proc|Func|Infix)=>self.parse_function(),
             Token::Keyword(Scope)=>self.parse_scope(),
+            Token::Keyword(Loop)=>self.parse_loop(),
             Token::Keyword(Disown)=>self.parse_disown(),
+            Token::Keyword(AssertType)=>self.parse_assert_type(),
             Token::Keyword(If)=>self.parse_if_else(),
             Token::Keyword(Cond)=>self.parse_cond(),
             Token::Keyword(Return)=>self.parse_return(),
+            Token::Keyword(Break)=>self.parse_break(),
+            Token::Keyword(Continue)=>self.parse_continue(),
+            Token::Hash=>self.parse_cfg(),
+            // `type` is reserved, but there is nothing to define a type as until records exist
+            Token::Keyword(Type)=>self.error("Type definitions are not supported yet"),
 
             Token::Whitespace(_)=>self.error("Internal error: Unexpected indent"),
             _=>{
-                let ret = self.parse_expr(0).map(Stmt::Expr)?;
+                let expr = self.parse_expr(0)?;
                 self.skip_ws();
+                self.where_bindings = self.parse_where()?;
+                let semicolon = self.peek(0) == Token::Semicolon;
                 self.eol()?;
-                Ok(ret)
+                Ok(Stmt::Expr(expr, semicolon))
             },
         }
     }
 
     fn parse_return(&mut self)->ParseResult<Stmt> {
         self.match_token(Token::Keyword(Return))?;
-        self.ws()?;
+        self.skip_ws();
 
-        if self.try_match(Token::Newline) {
+        if let Token::Newline|Token::Semicolon|Token::EOF = self.peek(0) {
+            self.eol()?;
             return Ok(Stmt::Return(None));
-        } else {
-            return self.parse_expr(0)
-                .map(Option::Some)
-                .map(Stmt::Return);
         }
+
+        let expr = self.parse_expr(0)?;
+
+        self.skip_ws();
+        self.where_bindings = self.parse_where()?;
+        self.eol()?;
+
+        return Ok(Stmt::Return(Some(expr)));
+    }
+
+    /// Parses an optional `where a = x, b = y` clause into the `let`s it stands for.
+    fn parse_where(&mut self)->ParseResult<Vec<Stmt>> {
+        let mut bindings = Vec::new();
+        if !self.try_match(Token::Keyword(Where)) {
+            return Ok(bindings);
+        }
+        self.ws()?;
+
+        loop {
+            let pattern = self.parse_pattern()?;
+            self.skip_ws();
+            self.match_token(Token::Assign)?;
+            self.skip_ws();
+
+            bindings.push(Stmt::VarDef {
+                mutable: false,
+                pattern,
+                ty: None,
+                data: Some(self.parse_expr(0)?),
+            });
+
+            self.skip_ws();
+            if !self.try_match(Token::Comma) {
+                break;
+            }
+            self.skip_ws();
+        }
+
+        return Ok(bindings);
+    }
+
+    fn parse_break(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Break))?;
+        self.skip_ws();
+
+        let label = self.try_label();
+        self.skip_ws();
+
+        let value = match self.peek(0) {
+            Token::Newline|Token::Semicolon|Token::EOF=>None,
+            _=>Some(self.parse_expr(0)?),
+        };
+
+        self.skip_ws();
+        self.eol()?;
+
+        return Ok(Stmt::Break(label, value));
+    }
+
+    fn parse_continue(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Continue))?;
+        self.skip_ws();
+
+        let label = self.try_label();
+
+        self.skip_ws();
+        self.eol()?;
+
+        return Ok(Stmt::Continue(label));
     }
 
     fn parse_cond(&mut self)->ParseResult<Stmt> {
         self.match_token(Token::Keyword(Cond))?;
+        let span = self.inner.span();
         self.match_token(Token::Newline)?;
         self.skip_nl();
 
         let mut conditions = Vec::new();
         let mut actions = Vec::new();
+        let mut default = None;
 
         let current_indent = *self.ws_stack.last();
         let indent;
@@ -223,35 +674,44 @@ impl<'a> Parser<'a> {
             match self.peek(0) {
                 Token::Whitespace(amt)=>{
                     if amt < indent {
+                        self.check_dedent(indent, current_indent)?;
                         break;
                     }
                     if amt > indent {
-                        return self.error("Unexpected indent");
+                        self.skip_misindented(indent);
+                        continue;
                     }
                     self.indent()?;
                 },
                 _=>break,
             }
 
+            // the `else` arm has to be the last one
+            if self.try_match(Token::Keyword(Else)) {
+                if conditions.len() == 0 {
+                    return self.error("`cond` needs at least one arm before `else`");
+                }
+                self.skip_ws();
+                self.match_token(Token::FatArrow)?;
+                self.skip_ws();
+
+                default = Some(self.parse_cond_action()?);
+
+                if let Token::Whitespace(amt) = self.peek(0) {
+                    if amt == indent {
+                        return self.error("`else` must be the last arm of a `cond`");
+                    }
+                }
+                break;
+            }
+
             conditions.push(self.parse_expr(0)?);
 
             self.skip_ws();
             self.match_token(Token::FatArrow)?;
             self.skip_ws();
 
-            match self.peek(0) {
-                Token::Keyword(Scope)=>{
-                    self.next();
-                    self.match_token(Token::Newline)?;
-                    self.skip_nl();
-                    actions.push(ConditionalAction::Scope(self.parse_block()?));
-                },
-                _=>{
-                    actions.push(ConditionalAction::Expr(self.parse_expr(0)?));
-                    // self.match_token(Token::Newline)?;
-                    self.skip_nl();
-                },
-            }
+            actions.push(self.parse_cond_action()?);
         }
 
         self.ws_stack.pop();
@@ -259,11 +719,45 @@ impl<'a> Parser<'a> {
         return Ok(Stmt::Conditional {
             conditions,
             actions,
+            default,
+            span,
         });
     }
 
+    fn parse_cond_action(&mut self)->ParseResult<ConditionalAction> {
+        match self.peek(0) {
+            Token::Keyword(Scope)=>{
+                self.next();
+                self.match_token(Token::Newline)?;
+                self.skip_nl();
+                Ok(ConditionalAction::Scope(self.parse_block()?))
+            },
+            Token::Keyword(Cond)=>{
+                let cond = self.parse_cond()?;
+                Ok(ConditionalAction::Cond(Box::new(cond)))
+            },
+            _=>{
+                let expr = self.parse_expr(0)?;
+                self.skip_ws();
+                let mut bindings = self.parse_where()?;
+
+                // the bindings need a block to go in
+                let action = if bindings.len() == 0 {
+                    ConditionalAction::Expr(expr)
+                } else {
+                    bindings.push(Stmt::Expr(expr, false));
+                    ConditionalAction::Scope(Block(bindings))
+                };
+                // self.match_token(Token::Newline)?;
+                self.skip_nl();
+                Ok(action)
+            },
+        }
+    }
+
     fn parse_if_else(&mut self)->ParseResult<Stmt> {
         self.match_token(Token::Keyword(If))?;
+        let span = self.inner.span();
         self.ws()?;
 
         let condition = self.parse_expr(0)?;
@@ -293,6 +787,7 @@ impl<'a> Parser<'a> {
             condition,
             block,
             default,
+            span,
         });
     }
 
@@ -307,88 +802,411 @@ impl<'a> Parser<'a> {
         return Ok(Stmt::Disown(expr));
     }
 
-    fn parse_scope(&mut self)->ParseResult<Stmt> {
-        self.match_token(Token::Keyword(Scope))?;
-        self.skip_ws();
-        self.match_token(Token::Newline)?;
-        self.skip_nl();
-
-        return self.parse_block().map(Stmt::Scope);
-    }
-
-    fn parse_var_def(&mut self)->ParseResult<Stmt> {
-        self.match_token(Token::Keyword(Let))?;
+    fn parse_assert_type(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(AssertType))?;
         self.ws()?;
 
-        let mutable = self.try_match(Token::Keyword(Mut));
-        if mutable {self.ws()?}
+        let expr = self.parse_expr(0)?;
+        self.skip_ws();
 
-        let name = self.word()?;
+        self.match_token(Token::Colon)?;
         self.skip_ws();
 
-        let data = if self.try_match(Token::Assign) {
-            self.skip_ws();
-            Some(self.parse_expr(0)?)
-        } else {
-            None
-        };
+        let ty = self.type_name()?;
 
+        self.skip_ws();
         self.eol()?;
 
-        return Ok(Stmt::VarDef {
-            mutable,
-            name,
-            data,
-        });
+        return Ok(Stmt::AssertType {expr, ty});
     }
 
-    fn parse_var_set(&mut self)->ParseResult<Stmt> {
-        self.match_token(Token::Keyword(Set))?;
-        self.ws()?;
-
-        let name = self.word()?;
+    fn parse_scope(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Scope))?;
         self.skip_ws();
 
-        self.match_token(Token::Assign)?;
-        self.skip_ws();
+        let label = self.try_label();
 
-        let data = self.parse_expr(0)?;
+        self.skip_ws();
+        self.match_token(Token::Newline)?;
+        self.skip_nl();
 
-        self.eol()?;
+        let block = self.parse_block()?;
 
-        return Ok(Stmt::VarSet {name, data});
+        return Ok(Stmt::Scope {
+            label,
+            block,
+        });
     }
 
-    fn parse_function(&mut self)->ParseResult<Stmt> {
-        let is_proc = match self.next() {
-            Token::Keyword(Proc)=>true,
-            Token::Keyword(Func)=>false,
-            _=>unreachable!("Function keyword"),
-        };
+    fn parse_loop(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Loop))?;
+        let span = self.inner.span();
+        self.skip_ws();
 
-        self.ws()?;
+        let label = self.try_label();
 
-        let name = self.word()?;
+        self.skip_ws();
+        self.match_token(Token::Newline)?;
+        self.skip_nl();
 
+        let block = self.parse_block()?;
+
+        return Ok(Stmt::Loop {
+            label,
+            block,
+            span,
+        });
+    }
+
+    /// Parses a `scope` that gives a value. It needs a label, so a `break` in a `scope` nested
+    /// inside of it can still give it the value.
+    fn parse_scope_expr(&mut self)->ParseResult<Expr> {
+        self.match_token(Token::Keyword(Scope))?;
         self.skip_ws();
 
-        let mut pattern = self.parse_pattern()?;
+        let label = match self.try_label() {
+            Some(label)=>label,
+            None=>return self.error("A `scope` that gives a value needs a label"),
+        };
+
+        self.skip_ws();
         self.match_token(Token::Newline)?;
         self.skip_nl();
 
         let block = self.parse_block()?;
 
+        return Ok(Expr::Scope {label, block});
+    }
+
+    fn parse_var_def(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Let))?;
+        self.ws()?;
+
+        let mutable = self.try_match(Token::Keyword(Mut));
+        if mutable {self.ws()?}
+
+        let pattern = self.parse_pattern()?;
+        self.skip_ws();
+
+        let mut ty = None;
+        if self.try_match(Token::Colon) {
+            self.skip_ws();
+            ty = Some(self.type_name()?);
+            self.skip_ws();
+        }
+
+        let data = if self.try_match(Token::Assign) {
+            self.skip_ws();
+
+            // the block ends the statement, so there is no end of line to match
+            if self.peek(0) == Token::Keyword(Scope) {
+                let data = self.parse_scope_expr()?;
+                return Ok(Stmt::VarDef {
+                    mutable,
+                    pattern,
+                    ty,
+                    data: Some(data),
+                });
+            }
+
+            Some(self.parse_expr(0)?)
+        } else {
+            None
+        };
+
+        self.eol()?;
+
+        return Ok(Stmt::VarDef {
+            mutable,
+            pattern,
+            ty,
+            data,
+        });
+    }
+
+    fn parse_const(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Const))?;
+        if *self.ws_stack.last() != 0 {
+            return self.error("`const` can only be declared at the top level of the file");
+        }
+        self.ws()?;
+
+        let name = self.word()?;
+        let span = self.inner.span();
+        self.skip_ws();
+
+        self.match_token(Token::Assign)?;
+        self.skip_ws();
+
+        let data = self.parse_expr(0)?;
+
+        self.eol()?;
+
+        return Ok(Stmt::Const {name, span, data});
+    }
+
+    fn parse_var_set(&mut self)->ParseResult<Stmt> {
+        self.match_token(Token::Keyword(Set))?;
+        self.ws()?;
+
+        let name = self.word()?;
+        let span = self.inner.span();
+        self.skip_ws();
+
+        let mut targets = vec![name];
+        let mut spans = vec![span.clone()];
+        while self.try_match(Token::Comma) {
+            self.skip_ws();
+            targets.push(self.word()?);
+            spans.push(self.inner.span());
+            self.skip_ws();
+        }
+
+        self.match_token(Token::Assign)?;
+        self.skip_ws();
+
+        let data = self.parse_expr(0)?;
+
+        self.eol()?;
+
+        if targets.len() > 1 {
+            return Ok(Stmt::VarSetMulti {targets, spans, data});
+        }
+
+        return Ok(Stmt::VarSet {name, span, data});
+    }
+
+    /// Parses a `#[cfg(feature)]` marker and the statement after it. The bindings of the
+    /// statement's `where` clause are gated by the same feature.
+    fn parse_cfg(&mut self)->ParseResult<Stmt> {
+        let indent = *self.ws_stack.last();
+
+        self.match_token(Token::Hash)?;
+        self.match_token(Token::SquareStart)?;
+        match self.next() {
+            Token::Word("cfg")=>{},
+            Token::Word(marker)=>return self.error(format!("Unknown marker `{marker}`")),
+            _=>return self.error("Expected `cfg`"),
+        }
+        self.match_token(Token::ParenStart)?;
+        let feature = self.word()?;
+        self.match_token(Token::ParenEnd)?;
+        self.match_token(Token::SquareEnd)?;
+
+        self.match_token(Token::Newline)?;
+        self.skip_nl();
+        if indent > 0 {
+            self.try_indent(indent)?;
+        }
+
+        let stmt = self.parse_stmt()?;
+        self.where_bindings = std::mem::take(&mut self.where_bindings)
+            .into_iter()
+            .map(|binding|Stmt::Cfg {
+                feature,
+                stmt: Box::new(binding),
+            })
+            .collect();
+
+        return Ok(Stmt::Cfg {
+            feature,
+            stmt: Box::new(stmt),
+        });
+    }
+
+    /// The `///` lines right above the line that `pos` is on, without the slashes and the space
+    /// after them. Anywhere else, and with more than three slashes, they are ordinary comments.
+    fn doc_comment(&self, pos: usize)->Option<String> {
+        let line_start = self.source[..pos].rfind('\n').map_or(0, |i|i + 1);
+        let mut lines = self.source[..line_start]
+            .lines()
+            .rev()
+            .map(str::trim_start)
+            .take_while(|line|line.starts_with("///") && !line.starts_with("////"))
+            .map(|line|line[3..].strip_prefix(' ').unwrap_or(&line[3..]))
+            .collect::<Vec<_>>();
+
+        if lines.len() == 0 {
+            return None;
+        }
+        lines.reverse();
+
+        return Some(lines.join("\n"));
+    }
+
+    fn parse_function(&mut self)->ParseResult<Stmt> {
+        // `infix 8 func <+> (a, b)` declares `<+>` as an operator with the given left binding power
+        let infix = if self.try_match(Token::Keyword(Infix)) {
+            self.ws()?;
+
+            let prec = match self.peek(0) {
+                Token::Number(n)=>{
+                    self.next();
+                    let prec = self.parse_num(n)?;
+                    self.ws()?;
+                    match u8::try_from(prec) {
+                        Ok(prec) if prec < u8::MAX=>prec,
+                        _=>return self.error("Operator precedence must be from 0 to 254"),
+                    }
+                },
+                _=>DEFAULT_INFIX_PREC,
+            };
+
+            Some(prec)
+        } else {
+            None
+        };
+
+        let is_proc = match self.next() {
+            Token::Keyword(Proc)=>true,
+            Token::Keyword(Func)=>false,
+            _=>return self.error("Expected `func` or `proc`"),
+        };
+        let doc = self.doc_comment(self.inner.span().start);
+
+        self.ws()?;
+
+        let (name, symbol) = match (self.next(), infix) {
+            (Token::Word(w), None)=>(self.intern(w), None),
+            (Token::Symbol(s), Some(_))=>(self.intern(s), Some(s)),
+            (Token::Symbol(_), None)=>return self.error("Operators must be declared with `infix`"),
+            (_, Some(_))=>return self.error("Expected an operator like `<+>`"),
+            _=>return self.error("Expected word"),
+        };
+        let span = self.inner.span();
+
+        self.skip_ws();
+
+        // `func f` takes no arguments, just like `func f ()`
+        let (pattern, defaults) = match self.peek(0) {
+            Token::Newline|Token::EOF|Token::Arrow|Token::Keyword(If)=>{
+                (Pattern::Group(Vec::new()), Vec::new())
+            },
+            _=>self.parse_params()?,
+        };
+        self.skip_ws();
+
+        let return_type = if self.try_match(Token::Arrow) {
+            self.ws()?;
+            let ty = self.parse_return_type()?;
+            self.skip_ws();
+            Some(ty)
+        } else {
+            None
+        };
+
+        // the operator can be used from here on, including in its own body
+        if let (Some(symbol), Some(prec)) = (symbol, infix) {
+            if pattern.arity() != 2 {
+                return self.error("Infix operators must take two arguments");
+            }
+            self.operators.insert(Token::Symbol(symbol), Operator::Apply, (prec, prec + 1));
+        }
+
+        let guard = if self.try_match(Token::Keyword(If)) {
+            self.ws()?;
+            Some(self.parse_expr(0)?)
+        } else {
+            None
+        };
+
+        // the header can be the last line of the file, like any other statement
+        if self.peek(0) != Token::EOF {
+            self.match_token(Token::Newline)?;
+        }
+        self.skip_nl();
+
+        // a function without an indented body has an empty one
+        let block = match self.peek(0) {
+            Token::Whitespace(amt) if amt > *self.ws_stack.last()=>self.parse_block()?,
+            _=>Block(Vec::new()),
+        };
+
         return Ok(Stmt::FunctionDef {
             is_proc,
+            infix,
             name,
+            span,
             pattern,
+            defaults,
+            return_type,
+            guard,
+            doc,
             block,
         });
     }
 
+    /// Parses the pattern of a function. The last names in its group can have default values, like
+    /// `(x, y = 0)`, which are returned in order.
+    fn parse_params(&mut self)->ParseResult<(Pattern, Vec<Expr>)> {
+        if !self.try_match(Token::ParenStart) {
+            return Ok((self.parse_pattern()?, Vec::new()));
+        }
+
+        let mut items = Vec::new();
+        let mut defaults = Vec::new();
+        loop {
+            self.skip_ws_nl();
+            if self.try_match(Token::ParenEnd) {
+                break;
+            }
+
+            let item = self.parse_pattern()?;
+            self.skip_ws_nl();
+
+            if matches!(item, Pattern::Name(..)) && self.try_match(Token::Assign) {
+                self.skip_ws_nl();
+                defaults.push(self.parse_expr(0)?);
+                self.skip_ws_nl();
+            } else if defaults.len() > 0 {
+                return self.error("Only the last parameters can have default values");
+            }
+            items.push(item);
+
+            match self.next() {
+                Token::ParenEnd=>break,
+                Token::Comma=>{},
+                _=>return self.error("Expected `)` or `,` in pattern"),
+            }
+        }
+
+        return Ok((Pattern::Group(items), defaults));
+    }
+
+    /// A type name, or a parenthesized list of `name: Type` outputs.
+    fn parse_return_type(&mut self)->ParseResult<ReturnType> {
+        if !self.try_match(Token::ParenStart) {
+            return Ok(ReturnType::Type(self.type_name()?));
+        }
+
+        let mut outputs = Vec::new();
+        loop {
+            self.skip_ws_nl();
+            if self.try_match(Token::ParenEnd) {
+                break;
+            }
+
+            let name = self.word()?;
+            self.skip_ws();
+            self.match_token(Token::Colon)?;
+            self.skip_ws();
+            outputs.push((name, self.type_name()?));
+            self.skip_ws_nl();
+
+            match self.next() {
+                Token::Comma=>{},
+                Token::ParenEnd=>break,
+                _=>return self.error("Expected `,` or `)` in return type"),
+            }
+        }
+
+        return Ok(ReturnType::Named(outputs));
+    }
+
     fn parse_block(&mut self)->ParseResult<Block> {
         let mut stmts = Vec::new();
         let mut indent = 0;
+        let mut parent = 0;
 
         while self.peek(0) != Token::EOF {
             self.skip_nl();
@@ -402,6 +1220,7 @@ impl<'a> Parser<'a> {
                         }
 
                         indent = self.indent()?;
+                        parent = last_indent;
                         self.ws_stack.push(indent);
                     },
                     _=>{
@@ -409,36 +1228,114 @@ impl<'a> Parser<'a> {
                         return self.error("Expected indented block");
                     },
                 }
-            } else if self.try_indent(indent).is_err() {
-                break;
+            } else {
+                match self.peek(0) {
+                    Token::Whitespace(amt) if amt > indent=>{
+                        self.skip_misindented(indent);
+                        continue;
+                    },
+                    _=>if self.try_indent(indent).is_err() {
+                        self.check_dedent(indent, parent)?;
+                        break;
+                    },
+                }
             }
 
-            stmts.push(self.parse_stmt()?);
+            let stmt = self.parse_stmt()?;
+            stmts.append(&mut self.where_bindings);
+            stmts.push(stmt);
         }
 
-        self.ws_stack.pop();
+        // nothing was pushed if the block ended before it started
+        if indent != 0 {
+            self.ws_stack.pop();
+        }
 
         return Ok(Block(stmts));
     }
 
     pub fn parse_expr(&mut self, min_prec: u8)->ParseResult<Expr> {
+        // the binding power of the outermost operator so far, starting with a prefix operator's
+        let mut top = match self.peek(0) {
+            Token::Sub=>Some((u8::MAX, min_prec.max(8))),
+            Token::Mul|Token::And|Token::Keyword(Heap|Stack|TypeOf)=>Some((u8::MAX, min_prec)),
+            _=>None,
+        };
+        // parentheses around a single expression that aren't needed on the left
+        let mut paren = None;
+
         let mut ret = match self.peek(0) {
             Token::Mul=>{
                 self.next();
                 let inner = self.parse_expr(min_prec)?;
                 Expr::Deref(Box::new(inner))
             },
+            Token::Sub=>{
+                self.next();
+                match self.peek(0) {
+                    // `i64::MIN` is only in range once it is negated, so it is read as one literal
+                    Token::Number(num_str) if is_min_magnitude(num_str)=>{
+                        self.next();
+                        Expr::Number(i64::MIN, Radix::split(num_str).0)
+                    },
+                    _=>{
+                        // negation binds tighter than `+` and `-`, so `-a + b` is `(-a) + b`
+                        let inner = self.parse_expr(min_prec.max(8))?;
+                        Expr::Negate(Box::new(inner))
+                    },
+                }
+            },
             Token::And=>{
                 self.next();
+                if self.try_match(Token::Keyword(Mut)) {
+                    self.ws()?;
+                    let inner = self.parse_expr(min_prec)?;
+                    Expr::BorrowMut(Box::new(inner))
+                } else {
+                    let inner = self.parse_expr(min_prec)?;
+                    Expr::Borrow(Box::new(inner))
+                }
+            },
+            Token::Keyword(Heap)=>{
+                self.next();
+                self.ws()?;
+                let inner = self.parse_expr(min_prec)?;
+                Expr::Heap(Box::new(inner))
+            },
+            Token::Keyword(Stack)=>{
+                self.next();
+                self.ws()?;
+                let inner = self.parse_expr(min_prec)?;
+                Expr::Stack(Box::new(inner))
+            },
+            Token::Keyword(TypeOf)=>{
+                self.next();
+                self.ws()?;
                 let inner = self.parse_expr(min_prec)?;
-                Expr::Borrow(Box::new(inner))
+                Expr::TypeOf(Box::new(inner))
             },
             Token::ParenStart=>{
                 self.next();
-                let mut ret = None;
+                let start = self.inner.span().start;
+                let mut items = Vec::new();
+                let mut trailing_comma = false;
+                let mut section = None;
+                let mut inner_prec = None;
+
+                self.skip_ws_nl();
+                if self.at_right_section() {
+                    let token = self.next();
+                    let span = self.inner.span();
+                    self.skip_ws_nl();
+                    let right = self.parse_expr(0)?;
+                    self.skip_ws_nl();
+                    self.match_token(Token::ParenEnd)?;
+
+                    section = Some(self.section(token, None, Some(right), span)?);
+                }
 
-                loop {
-                    self.skip_ws();
+                while section.is_none() {
+                    self.skip_ws_nl();
 
                     match self.peek(0) {
                         Token::ParenEnd=>{
@@ -448,45 +1345,129 @@ impl<'a> Parser<'a> {
                         _=>{},
                     }
 
-                    self.skip_ws();
-                    // If we are already a tuple, then add another item. Otherwise create a tuple.
-                    match &mut ret {
-                        Some(Expr::Group(items))=>items.push(self.parse_expr(0)?),
-                        Some(_)=>ret = Some(Expr::Group(vec![ret.unwrap(), self.parse_expr(0)?])),
-                        None=>ret = Some(self.parse_expr(0)?),
+                    items.push(self.parse_expr(0)?);
+                    inner_prec = self.last_prec;
+                    trailing_comma = false;
+                    self.skip_ws_nl();
+
+                    if items.len() == 1 && self.at_left_section() {
+                        let token = self.next();
+                        let span = self.inner.span();
+                        self.next();
+
+                        let left = items.pop().unwrap();
+                        section = Some(self.section(token, Some(left), None, span)?);
+                        break;
                     }
 
                     // Check for paren end or comma to start a list or end it.
                     match self.next() {
-                        Token::Comma=>{},
+                        Token::Comma=>trailing_comma = true,
                         Token::ParenEnd=>break,
                         _=>return self.error("Expected `,` or `)` in group"),
                     }
                 }
 
-                ret.unwrap_or(Expr::Group(Vec::new()))
+                // Parentheses around a single expression only group it, but `(x,)` is a tuple
+                if let Some(section) = section {
+                    section
+                } else if items.len() == 1 && !trailing_comma {
+                    let span = start..self.inner.span().end;
+                    if inner_prec.map_or(true, |(l, _)|l >= min_prec) {
+                        paren = Some((inner_prec, span));
+                    }
+
+                    let inner = items.pop().unwrap();
+                    if self.preserve_parens {
+                        Expr::Paren(Box::new(inner))
+                    } else {
+                        inner
+                    }
+                } else {
+                    Expr::Group(items)
+                }
+            },
+            Token::SquareStart=>{
+                self.next();
+                let mut items = Vec::new();
+
+                loop {
+                    self.skip_ws_nl();
+                    if self.try_match(Token::SquareEnd) {
+                        break;
+                    }
+
+                    items.push(self.parse_expr(0)?);
+                    self.skip_ws_nl();
+
+                    match self.next() {
+                        Token::Comma=>{},
+                        Token::SquareEnd=>break,
+                        _=>return self.error("Expected `,` or `]` in list"),
+                    }
+                }
+
+                Expr::List(items)
             },
             _=>self.parse_expr_terminal()?,
         };
 
         loop {
+            if let (Token::Whitespace(_), Token::Keyword(As)) = (self.peek(0), self.peek(1)) {
+                self.check_paren(&mut paren, Some(CAST_PREC));
+                if CAST_PREC < min_prec {
+                    break;
+                }
+
+                self.skip_ws();
+                self.next();
+                self.ws()?;
+
+                ret = Expr::Cast {
+                    expr: Box::new(ret),
+                    ty: self.type_name()?,
+                };
+                top = Some((CAST_PREC, u8::MAX));
+                continue;
+            }
+
+            // the operator of a left section like `(a +)` is handled by the group
+            if self.at_left_section() {
+                break;
+            }
+
             // Get the token's precedence
             let l_prec;
             let r_prec;
             match self.peek(0) {
                 // If we have a whitespace, then check if there is a another operator after it.
+                // Application never spans lines: the lexer folds trailing whitespace into the
+                // `Newline` token, so a `Newline` always ends the expression here.
+                //
+                // An operator with whitespace on both sides is binary, so `a - 1` is subtraction.
+                // A prefix operator with whitespace only before it starts an argument, so `f -1`
+                // is `f (-1)` and `f &x` is `f (&x)`.
                 Token::Whitespace(_)=>{
                     let prec;
                     let peek = self.peek(1);
-                    if let Some(p) = Self::infix_prec(peek) {
+                    let prefix_arg = Self::is_prefix_op(peek) && !matches!(
+                        self.peek(2),
+                        Token::Whitespace(_)|Token::Newline,
+                    );
+                    let apply_prec = self.infix_prec(Token::Whitespace(0));
+                    if let (true, Some(p)) = (prefix_arg, apply_prec) {
+                        prec = p;
+                    } else if let Some(p) = self.infix_prec(peek) {
                         // If there is an operator after the whitespace, then skip it and get the
                         // next operator's precedence.
                         self.skip_ws();
                         prec = p;
-                    } else if self.is_token_expr_start(peek) {
+                    } else if let (true, Some(p)) = (self.is_token_expr_start(peek), apply_prec) {
                         // If the token after the whitespace is an expr terminal or prefix
                         // operator, then get the whitespace's precedence
-                        prec = Self::infix_prec(Token::Whitespace(0)).unwrap();
+                        prec = p;
+                    } else if let Token::Symbol(symbol) = peek {
+                        return self.error(format!("Operator `{symbol}` is not declared"));
                     } else {
                         // Otherwise, it isn't an expr, so break the loop.
                         break;
@@ -495,37 +1476,46 @@ impl<'a> Parser<'a> {
                     r_prec = prec.1;
                 }
                 // For every other token, check it.
-                t=>if let Some((l, r)) = Self::infix_prec(t) {
+                t=>if let Some((l, r)) = self.infix_prec(t) {
                     l_prec = l;
                     r_prec = r;
+                } else if let Token::Symbol(symbol) = t {
+                    return self.error(format!("Operator `{symbol}` is not declared"));
                 } else {break},
             }
 
+            self.check_paren(&mut paren, Some(l_prec));
+
             // If the precedence is too low, then stop the loop.
             if l_prec < min_prec {
                 break;
             }
 
             // Get the operator
-            let op = Self::infix_op(self.next());
+            let token = self.next();
+            let span = self.inner.span();
             self.skip_ws();
 
             // Parse the right side and wrap the expression.
-            ret = Expr::Operation {
-                op,
-                left: Box::new(ret),
-                right: Box::new(self.parse_expr(r_prec)?),
-            };
+            let right = self.parse_expr(r_prec)?;
+            ret = self.binary(token, ret, right, span)?;
+            top = Some((l_prec, r_prec));
         }
 
         // postfix operations here
         while let Some((l_prec, r_prec)) = Self::postfix_prec(self.peek(0)) {
+            self.check_paren(&mut paren, Some(l_prec));
             if l_prec < min_prec {
                 break;
             }
+            top = Some((l_prec, r_prec));
 
             match self.next() {
                 Token::FieldIndex=>{
+                    // numbers are only integers, so `1.5` and hex floats like `0x1.8p3` are errors
+                    if let (Expr::Number(..), Token::Number(_)) = (&ret, self.peek(0)) {
+                        return self.error("Float literals are not supported yet");
+                    }
                     ret = Expr::Field {
                         left: Box::new(ret),
                         name: self.word()?,
@@ -533,7 +1523,7 @@ impl<'a> Parser<'a> {
                 },
                 tok=>{
                     ret = Expr::Operation {
-                        op: Self::postfix_op(tok),
+                        op: self.postfix_op(tok)?,
                         left: Box::new(ret),
                         right: Box::new(self.parse_expr(r_prec)?),
                     };
@@ -541,20 +1531,108 @@ impl<'a> Parser<'a> {
             }
         }
 
+        self.check_paren(&mut paren, None);
+        self.last_prec = top;
+
         return Ok(ret);
     }
 
     fn is_token_expr_start(&self, token: Token)->bool {
         use Token::*;
         match token {
-            Word(_)|Number(_)|String(_)|Mul|And|ParenStart=>true,
+            Word(_)|Number(_)|String(_)|Char(_)|Mul|And|Sub|ParenStart|SquareStart=>true,
+            _=>false,
+        }
+    }
+
+    /// Operators that can start an expression as well as join two.
+    fn is_prefix_op(token: Token)->bool {
+        match token {
+            Token::Mul|Token::And|Token::Sub=>true,
             _=>false,
         }
     }
 
-    fn postfix_op(token: Token)->Operator {
+    /// Checks if the group starts with an operator, making it a right section like `(+ 1)`. A
+    /// prefix operator must have whitespace after it, so `(-1)` is still negation.
+    fn at_right_section(&mut self)->bool {
+        let token = self.peek(0);
+        let spaced = matches!(self.peek(1), Token::Whitespace(_)|Token::Newline);
+        if Self::is_prefix_op(token) && !spaced {
+            return false;
+        }
+
+        return self.is_section_op(token);
+    }
+
+    /// Checks if the next operator is directly followed by `)`, ending a left section like `(a +)`.
+    fn at_left_section(&mut self)->bool {
+        match self.peek(0) {
+            Token::Whitespace(_)=>{
+                let token = self.peek(1);
+                self.is_section_op(token) && self.peek(2) == Token::ParenEnd
+            },
+            token=>self.is_section_op(token) && self.peek(1) == Token::ParenEnd,
+        }
+    }
+
+    /// Function application has no token, so it can't be sectioned.
+    fn is_section_op(&self, token: Token)->bool {
+        match token {
+            Token::Whitespace(_)=>false,
+            _=>self.infix_prec(token).is_some(),
+        }
+    }
+
+    /// Builds the `func` that an operator section desugars to. The missing side is the argument.
+    fn section(
+        &mut self,
+        token: Token<'a>,
+        left: Option<Expr>,
+        right: Option<Expr>,
+        span: Range<usize>,
+    )->ParseResult<Expr> {
+        let param = self.intern("$arg");
+        let arg = ||Expr::Var(param, span.clone());
+        let left = left.unwrap_or_else(arg);
+        let right = right.unwrap_or_else(arg);
+
+        return Ok(Expr::Lambda {
+            name: self.intern("<section>"),
+            param,
+            span: span.clone(),
+            body: Box::new(self.binary(token, left, right, span)?),
+        });
+    }
+
+    /// Joins two expressions with the operator spelled by the token. Operators declared with
+    /// `infix` call the function of the same name with both sides, so `a <+> b` is `<+> (a, b)`.
+    fn binary(
+        &mut self,
+        token: Token<'a>,
+        left: Expr,
+        right: Expr,
+        span: Range<usize>,
+    )->ParseResult<Expr> {
+        let op = self.infix_op(token)?;
+
+        return Ok(match token {
+            Token::Symbol(symbol)=>Expr::Operation {
+                op,
+                left: Box::new(Expr::Var(self.intern(symbol), span)),
+                right: Box::new(Expr::Group(vec![left, right])),
+            },
+            _=>Expr::Operation {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        });
+    }
+
+    fn postfix_op(&self, token: Token)->ParseResult<Operator> {
         match token {
-            _=>unreachable!("Postfix operator"),
+            _=>self.error("Expected postfix operator"),
         }
     }
 
@@ -565,51 +1643,32 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn infix_op(token: Token)->Operator {
-        match token {
-            Token::Equal=>Operator::Equal,
-            Token::NotEqual=>Operator::NotEqual,
-            Token::Less=>Operator::Less,
-            Token::LessEqual=>Operator::LessEqual,
-            Token::Greater=>Operator::Greater,
-            Token::GreaterEqual=>Operator::GreaterEqual,
-            Token::Add=>Operator::Add,
-            Token::Sub=>Operator::Sub,
-            Token::Mul=>Operator::Mul,
-            Token::Div=>Operator::Div,
-            Token::And=>Operator::And,
-            Token::Or=>Operator::Or,
-            Token::Xor=>Operator::Xor,
-            Token::Whitespace(_)=>Operator::Apply,
-            Token::Keyword(And)=>Operator::LogicAnd,
-            Token::Keyword(Or)=>Operator::LogicOr,
-            _=>unreachable!("Infix operator"),
-        }
-    }
-
-    fn infix_prec(token: Token)->Option<(u8, u8)> {
-        match token {
-            Token::Keyword(And|Or)=>Some((0, 1)),
-            Token::Equal|
-                Token::NotEqual|
-                Token::Less|
-                Token::LessEqual|
-                Token::Greater|
-                Token::GreaterEqual=>Some((2,3)),
-            Token::Whitespace(_)=>Some((5,4)),      // function application is left-associative
-            Token::Add|Token::Sub=>Some((6,7)),
-            Token::Mul|Token::Div=>Some((8,9)),
-            Token::And|Token::Or|Token::Xor=>Some((10,11)),
-            _=>None
+    fn infix_op(&self, token: Token)->ParseResult<Operator> {
+        match self.operators.get(token) {
+            Some(entry)=>Ok(entry.op),
+            None=>self.error("Expected infix operator"),
         }
     }
 
+    fn infix_prec(&self, token: Token)->Option<(u8, u8)> {
+        self.operators
+            .get(token)
+            .map(|entry|entry.prec)
+    }
+
     fn parse_expr_terminal(&mut self)->ParseResult<Expr> {
         match self.next() {
-            Token::Number(num_str)=>Ok(Expr::Number(self.parse_num(num_str)?)),
+            Token::Number(num_str)=>{
+                let radix = Radix::split(num_str).0;
+                Ok(Expr::Number(self.parse_num(num_str)?, radix))
+            },
             Token::Word("None")=>Ok(Expr::None),
-            Token::Word(word)=>Ok(Expr::Var(self.intern(word))),
+            Token::Word(word)=>Ok(Expr::Var(self.intern(word), self.inner.span())),
             Token::String(s)=>Ok(Expr::String(self.intern_string(s))),
+            Token::Char(c)=>Ok(Expr::Char(self.parse_char(c)?)),
+            // reserved for record literals and `{ base with field = value }` updates
+            Token::CurlyStart=>self.error("Records are not supported yet"),
+            Token::Keyword(Cond)=>self.error("`cond` can only be used as a statement"),
             _=>self.error("Expected `expr`"),
         }
     }
@@ -620,7 +1679,7 @@ impl<'a> Parser<'a> {
                 let mut items = Vec::new();
 
                 loop {
-                    self.skip_ws();
+                    self.skip_ws_nl();
 
                     match self.peek(0) {
                         Token::ParenEnd=>{
@@ -632,7 +1691,7 @@ impl<'a> Parser<'a> {
 
                     items.push(self.parse_pattern()?);
 
-                    self.skip_ws();
+                    self.skip_ws_nl();
 
                     match self.next() {
                         Token::ParenEnd=>break,
@@ -644,9 +1703,462 @@ impl<'a> Parser<'a> {
                 Pattern::Group(items)
             },
             Token::Word("None")=>Pattern::None,
-            Token::Word(w)=>Pattern::Name(self.intern(w)),
+            Token::Word(w)=>Pattern::Name(self.intern(w), self.inner.span()),
             Token::Number(n)=>Pattern::Number(self.parse_num(n)?),
             _=>return self.error("Unexpected token in pattern"),
         })
     }
 }
+
+/// Collects the spans of the lexer's errors, so they can be reported even though the parser never
+/// sees them.
+fn find_lex_errors<'a>(mut lexer: logos::Lexer<'a, Token<'a>>)->Vec<Range<usize>> {
+    let mut errors = Vec::new();
+    while let Some(token) = lexer.next() {
+        if token.is_err() {
+            errors.push(lexer.span());
+        }
+    }
+
+    return errors;
+}
+
+/// Returns true if the number literal is the magnitude of `i64::MIN`, which doesn't fit in an `i64`
+/// until it is negated.
+fn is_min_magnitude(num_str: &str)->bool {
+    let (radix, digits) = Radix::split(num_str);
+    let magnitude = u64::from_str_radix(&digits.replace('_', ""), radix.base());
+
+    return magnitude == Ok(i64::MIN.unsigned_abs());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of the `let` the source starts with.
+    fn let_value(source: &str)->Expr {
+        let (mut stmts, _, _) = Parser::new(source).parse_all().unwrap();
+
+        match stmts.remove(0) {
+            Stmt::VarDef{data: Some(data), ..}=>data,
+            stmt=>panic!("expected a `let` with a value, found {stmt:?}"),
+        }
+    }
+
+    /// The message of the first error from parsing the source.
+    fn first_error(source: &str)->Cow<'static, str> {
+        match Parser::new(source).parse_all() {
+            Ok(_)=>panic!("`{source}` should not parse"),
+            Err(mut errors)=>errors.remove(0).msg,
+        }
+    }
+
+    #[test]
+    fn char_escapes() {
+        assert!(matches!(let_value("let c = '\\x41'\n"), Expr::Char('A')));
+        assert!(matches!(let_value("let c = '\\u{1F600}'\n"), Expr::Char('\u{1F600}')));
+
+        assert_eq!(
+            first_error("let c = '\\x80'\n"),
+            "Hex escapes must be in the ASCII range `\\x00`-`\\x7F`",
+        );
+        assert_eq!(
+            first_error("let c = '\\u{D800}'\n"),
+            "Unicode escape is not a valid unicode scalar value",
+        );
+    }
+
+    #[test]
+    fn application_stops_at_newlines() {
+        let (stmts, _, _) = Parser::new("f x\ng y\n").parse_all().unwrap();
+
+        assert_eq!(stmts.len(), 2);
+        for stmt in &stmts {
+            assert!(matches!(stmt, Stmt::Expr(Expr::Operation{op: Operator::Apply, ..}, _)));
+        }
+    }
+
+    #[test]
+    fn cond_else_arms() {
+        let source = "cond\n    a => print 1\n    else => print 2\n";
+        let (stmts, _, _) = Parser::new(source).parse_all().unwrap();
+
+        let Stmt::Conditional{conditions, default, ..} = &stmts[0] else {
+            panic!("expected a `cond`, found {:?}", stmts[0]);
+        };
+        assert_eq!(conditions.len(), 1);
+        assert!(matches!(default, Some(ConditionalAction::Expr(_))));
+
+        assert!(Parser::new("cond\n    else => print 2\n    a => print 1\n").parse_all().is_err());
+    }
+
+    #[test]
+    fn redundant_parens_are_dropped() {
+        assert!(matches!(let_value("let y = ((x))\n"), Expr::Var(..)));
+
+        let Expr::Group(items) = let_value("let y = (x,)\n") else {
+            panic!("`(x,)` should be a 1-tuple");
+        };
+        assert!(matches!(items.as_slice(), [Expr::Var(..)]));
+    }
+
+    #[test]
+    fn spacing_decides_prefix_or_binary_operators() {
+        let op_and_right = |source: &str|match let_value(source) {
+            Expr::Operation{op, right, ..}=>(op, *right),
+            value=>panic!("expected an operation, found {value:?}"),
+        };
+
+        let (op, right) = op_and_right("let y = f -1\n");
+        assert_eq!(op, Operator::Apply);
+        assert!(matches!(right, Expr::Negate(_)));
+
+        let (op, right) = op_and_right("let y = a - 1\n");
+        assert_eq!(op, Operator::Sub);
+        assert!(matches!(right, Expr::Number(1, _)));
+
+        let (op, right) = op_and_right("let y = f &x\n");
+        assert_eq!(op, Operator::Apply);
+        assert!(matches!(right, Expr::Borrow(_)));
+    }
+
+    #[test]
+    fn custom_operators() {
+        let mut operators = OperatorTable::default();
+        operators.insert(Token::Tilde, Operator::Xor, (10, 11));
+
+        let parser = Parser::with_operators("let y = a ~ b * c\n", operators);
+        let (stmts, _, _) = parser.parse_all().unwrap();
+        let Stmt::VarDef{data: Some(Expr::Operation{op, left, ..}), ..} = &stmts[0] else {
+            panic!("expected a `let` of an operation, found {:?}", stmts[0]);
+        };
+        // it binds tighter than `*`
+        assert_eq!(*op, Operator::Mul);
+        assert!(matches!(&**left, Expr::Operation{op: Operator::Xor, ..}));
+
+        assert!(Parser::new("let y = a ~ b\n").parse_all().is_err());
+    }
+
+    #[test]
+    fn type_definitions_are_reserved() {
+        assert_eq!(first_error("type A = A\n"), "Type definitions are not supported yet");
+    }
+
+    #[test]
+    fn random_tokens_never_panic() {
+        const PIECES: &[&str] = &[
+            "let", "mut", "set", "func", "proc", "infix", "if", "else", "cond", "scope", "loop",
+            "break", "continue", "return", "where", "const", "as", "heap", "stack", "typeof",
+            "x", "f", "'l", "1", "0xF", "\"s\"", "'c'", "(", ")", "[", "]", "{", "}", ",", ";",
+            "=", "=>", "+", "-", "*", "/", "&", "|", "^", "<", "==", "#", ":", "\\", "///",
+            " ", "    ", "\n", "\n    ", "\n        ",
+        ];
+
+        // xorshift, so the inputs are the same every run
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut random = move |max: usize|{
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % max
+        };
+
+        for _ in 0..2000 {
+            let source = (0..random(40))
+                .map(|_|PIECES[random(PIECES.len())])
+                .collect::<String>();
+
+            let _ = Parser::new(&source).parse_all();
+        }
+    }
+
+    #[test]
+    fn the_interner_outlives_the_parser() {
+        let (stmts, interner) = Parser::new("let answer = 42\n").parse().unwrap();
+
+        let Stmt::VarDef{pattern: Pattern::Name(name, _), ..} = &stmts[0] else {
+            panic!("expected a `let` of a name, found {:?}", stmts[0]);
+        };
+        assert_eq!(interner.get_string(*name), "answer");
+    }
+
+    #[test]
+    fn backslashes_continue_lines() {
+        let (stmts, _, _) = Parser::new("let x = 1 + \\\n    2\nlet y = x\n").parse_all().unwrap();
+
+        assert_eq!(stmts.len(), 2);
+        let Stmt::VarDef{data: Some(Expr::Operation{op, right, ..}), ..} = &stmts[0] else {
+            panic!("expected a `let` of an operation, found {:?}", stmts[0]);
+        };
+        assert_eq!(*op, Operator::Add);
+        assert!(matches!(**right, Expr::Number(2, _)));
+    }
+
+    #[test]
+    fn float_literals_are_rejected() {
+        assert_eq!(first_error("let x = 0x1.8p3\n"), "Float literals are not supported yet");
+        assert_eq!(first_error("let x = 1.5\n"), "Float literals are not supported yet");
+    }
+
+    #[test]
+    fn crlf_and_lf_parse_the_same() {
+        let lf = concat!(
+            "/// doc\nfunc f x\n    // comment\n",
+            "    if x\n        x + \\\n            1\n",
+            "f 2\n",
+        );
+        let crlf = lf.replace('\n', "\r\n");
+        let cr = lf.replace('\n', "\r");
+
+        fn tokens(source: &str)->Vec<Token<'_>> {
+            crate::lexer::tokenize(source, true)
+                .into_iter()
+                .map(|(token, _)|token)
+                .collect()
+        }
+        assert_eq!(tokens(lf), tokens(&crlf));
+        assert_eq!(tokens(lf), tokens(&cr));
+
+        let (stmts, _, _) = Parser::new(lf).parse_all().unwrap();
+        for source in [crlf, cr] {
+            let (other, _, _) = Parser::new(&source).parse_all().unwrap();
+            assert_eq!(stmts.len(), other.len());
+        }
+    }
+
+    #[test]
+    fn records_are_reserved() {
+        assert_eq!(first_error("let p = { base with x = 5 }\n"), "Records are not supported yet");
+    }
+
+    #[test]
+    fn cond_is_only_a_statement() {
+        let source = "let x = cond\n    a => 1\n    else => 2\n";
+        assert_eq!(first_error(source), "`cond` can only be used as a statement");
+    }
+
+    #[test]
+    fn operator_sections_are_lambdas() {
+        let section = |source: &str|match let_value(source) {
+            Expr::Lambda{param, body, ..}=>match *body {
+                Expr::Operation{op: Operator::Add, left, right}=>{
+                    let is_param = |expr: &Expr|{
+                        matches!(expr, Expr::Var(name, _) if *name == param)
+                    };
+                    (is_param(&left), is_param(&right))
+                },
+                body=>panic!("expected an addition, found {body:?}"),
+            },
+            value=>panic!("expected a lambda, found {value:?}"),
+        };
+
+        // the argument fills in the missing side
+        assert_eq!(section("let inc = (+ 1)\n"), (true, false));
+        assert_eq!(section("let inc = (1 +)\n"), (false, true));
+    }
+
+    #[test]
+    fn untokenizable_characters_are_errors() {
+        let source = "let x = 1 @ 2\nlet y = $\n";
+        let errors = Parser::new(source)
+            .lex_errors()
+            .map(|error|(error.msg, error.span))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [
+            ("Unexpected character `@`".into(), 10..11),
+            ("Unexpected character `$`".into(), 22..23),
+        ]);
+
+        assert_eq!(first_error(source), "Unexpected character `@`");
+    }
+
+    #[test]
+    fn block_comments_between_arguments() {
+        let (stmts, interner, _) = Parser::new("f (a, /* the first */ b)\n").parse_all().unwrap();
+
+        let Stmt::Expr(Expr::Operation{op: Operator::Apply, right, ..}, _) = &stmts[0] else {
+            panic!("expected a call, found {:?}", stmts[0]);
+        };
+        let Expr::Group(args) = &**right else {
+            panic!("expected a tuple of arguments, found {right:?}");
+        };
+        let names = args.iter()
+            .map(|arg|match arg {
+                Expr::Var(name, _)=>interner.get_string(*name),
+                _=>panic!("expected a name, found {arg:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b"]);
+
+        // the newline inside the comment would otherwise be skipped and join the two lines
+        assert!(Parser::new("let a = 1 /* one\ntwo */\nlet b = 2\n").parse_all().is_err());
+    }
+
+    #[test]
+    fn number_literals_keep_their_radix() {
+        let literals = [
+            ("0xFF", 255, Radix::Hex),
+            ("0b101", 5, Radix::Binary),
+            ("0o17", 15, Radix::Octal),
+            ("1_000", 1000, Radix::Decimal),
+        ];
+
+        for (literal, value, radix) in literals {
+            match let_value(&format!("let x = {literal}\n")) {
+                Expr::Number(n, r)=>assert_eq!((n, r), (value, radix)),
+                expr=>panic!("expected a number, found {expr:?}"),
+            }
+        }
+
+        assert_eq!(Radix::Hex.format(255), "0xFF");
+        assert_eq!(Radix::Binary.format(5), "0b101");
+        assert_eq!(Radix::Octal.format(15), "0o17");
+    }
+
+    #[test]
+    fn misindented_lines_are_skipped() {
+        let source = concat!(
+            "let a = 1\n",
+            "    let b = 2\n",
+            "        let c = 3\n",
+            "let d = 4\n",
+            "  let e = 5\n",
+            "let f = 6\n",
+        );
+        let errors = match Parser::new(source).parse_all() {
+            Ok(_)=>panic!("misindented lines should be errors"),
+            Err(errors)=>errors.into_iter()
+                .map(|error|(error.msg, error.span))
+                .collect::<Vec<_>>(),
+        };
+
+        // the deeper line after the first one is skipped with it
+        assert_eq!(errors, [
+            ("Unexpected indent".into(), 10..14),
+            ("Unexpected indent".into(), 52..54),
+        ]);
+    }
+
+    #[test]
+    fn recovery_skips_newlines_inside_brackets() {
+        let mut parser = Parser::new("f (a,\n    b) c\n\nlet x = 1\n");
+        parser.recover_to_statement_boundary();
+        assert_eq!(parser.peek(0), Token::Keyword(Keyword::Let));
+
+        // both bad statements are reported and the good ones between them still parse
+        let errors = match Parser::new("let = 1\nlet a = (1,\n    2)\nlet = 2\n").parse_all() {
+            Ok(_)=>panic!("the `let`s without names should not parse"),
+            Err(errors)=>errors,
+        };
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn redundant_parens_are_warned_about() {
+        let warnings = |source: &str|{
+            let (_, _, warnings) = Parser::new(source).parse_all().unwrap();
+            warnings.into_iter()
+                .map(|warning|(warning.msg, warning.span))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(warnings("let x = (1 * 2) + 3\n"), [("Redundant parentheses".into(), 8..15)]);
+        assert_eq!(warnings("let x = (y)\n"), [("Redundant parentheses".into(), 8..11)]);
+        assert_eq!(warnings("let x = (1 + 2) * 3\n"), []);
+        assert_eq!(warnings("let x = (1, 2)\n"), []);
+    }
+
+    #[test]
+    fn dedents_must_match_an_outer_level() {
+        let source = "func f x\n    if x\n        let y = 1\n      let z = 2\n    x\n";
+        assert_eq!(first_error(source), "Unindent does not match any outer indentation level");
+
+        assert!(Parser::new("func f x\n    if x\n        let y = 1\n    x\n").parse_all().is_ok());
+    }
+
+    #[test]
+    fn parse_next_stmt_matches_parse_file() {
+        let source = concat!(
+            "func f x\n",
+            "    let y = x * 2\n",
+            "    y\n",
+            "let a = f 1\n",
+            "a + b where b = 2\n",
+            "if a > 1\n",
+            "    let c = a\n",
+        );
+        let whole = Parser::new(source).parse_file().unwrap();
+
+        let mut parser = Parser::new(source);
+        let mut one_at_a_time = Vec::new();
+        while let Some(stmt) = parser.parse_next_stmt() {
+            one_at_a_time.push(stmt.unwrap());
+        }
+
+        assert_eq!(format!("{one_at_a_time:?}"), format!("{whole:?}"));
+    }
+
+    #[test]
+    fn overflowing_literals_are_out_of_range() {
+        assert!(matches!(let_value("let x = 9223372036854775807\n"), Expr::Number(i64::MAX, _)));
+
+        let overflow = "Integer literal out of range for i64";
+        assert_eq!(first_error("let x = 9223372036854775808\n"), overflow);
+        assert_eq!(first_error("let x = 0x1_0000_0000_0000_0000\n"), overflow);
+
+        // only negating it brings `i64::MIN` in range
+        assert!(matches!(let_value("let x = -9223372036854775808\n"), Expr::Number(i64::MIN, _)));
+        assert!(matches!(let_value("let x = -0x8000_0000_0000_0000\n"), Expr::Number(i64::MIN, _)));
+        assert_eq!(first_error("let x = -9223372036854775809\n"), overflow);
+    }
+
+    #[test]
+    fn parens_can_be_preserved() {
+        let source = "let y = ((x)) + 1\n";
+        let (stmts, _, _) = Parser::new(source).preserve_parens().parse_all().unwrap();
+        let Stmt::VarDef{data: Some(Expr::Operation{left, ..}), ..} = &stmts[0] else {
+            panic!("expected an addition, found {:?}", stmts[0]);
+        };
+        let Expr::Paren(inner) = &**left else {
+            panic!("expected parentheses, found {left:?}");
+        };
+        assert!(matches!(&**inner, Expr::Paren(var) if matches!(**var, Expr::Var(..))));
+
+        // they are dropped by default
+        let Expr::Operation{left, ..} = let_value(source) else {
+            panic!("expected an addition");
+        };
+        assert!(matches!(*left, Expr::Var(..)));
+    }
+
+    #[test]
+    fn stray_doc_comments_are_ordinary_comments() {
+        let sources = [
+            "/// not a function\nlet x = 1\n",
+            "func f x\n    /// inside a body\n    let y = x\n    y\n",
+            "//// banner\nlet x = 1\n",
+            "let x = 1 /// note\n",
+            "let x = 1\n//",
+        ];
+        for source in sources {
+            assert!(Parser::new(source).parse_all().is_ok(), "`{source}` should parse");
+        }
+
+        let source = "//// banner\n/// Adds one.\ninfix func <+> (a, b)\n    a\n";
+        let (stmts, _, _) = Parser::new(source).parse_all().unwrap();
+        assert!(matches!(&stmts[0], Stmt::FunctionDef{doc: Some(doc), ..} if doc == "Adds one."));
+    }
+
+    #[test]
+    fn function_headers_can_end_the_file() {
+        for source in ["func f", "func f x", "let a = 1\nproc p (x, y) -> Number"] {
+            let (stmts, _, _) = Parser::new(source).parse_all().unwrap();
+            let Some(Stmt::FunctionDef{block: Block(body), ..}) = stmts.last() else {
+                panic!("`{source}` should end with a function, found {stmts:?}");
+            };
+            assert!(body.is_empty());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// the infix operators a parser accepts and how tightly they bind
+
+
+use crate::lexer::{
+    Token,
+    Keyword,
+};
+use super::Operator;
+
+
+/// An infix operator: the token that spells it, the operator it parses to, and its left and right
+/// binding power.
+#[derive(Debug, Copy, Clone)]
+pub struct OperatorEntry<'a> {
+    pub token: Token<'a>,
+    pub op: Operator,
+    pub prec: (u8, u8),
+}
+
+/// The infix operators a parser accepts. Function application is the `Whitespace` entry, and any
+/// whitespace token matches it regardless of its width. Operators declared with `infix` are
+/// `Symbol` entries with the `Apply` operator, because they call the function of the same name.
+#[derive(Debug, Clone)]
+pub struct OperatorTable<'a> {
+    entries: Vec<OperatorEntry<'a>>,
+}
+impl<'a> OperatorTable<'a> {
+    /// A table without any operators, not even application.
+    pub fn new()->Self {
+        OperatorTable {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds an operator, replacing any existing operator spelled with the same token.
+    pub fn insert(&mut self, token: Token<'a>, op: Operator, prec: (u8, u8)) {
+        let token = Self::key(token);
+        let entry = OperatorEntry {token, op, prec};
+
+        match self.entries.iter_mut().find(|e|e.token == token) {
+            Some(existing)=>*existing = entry,
+            None=>self.entries.push(entry),
+        }
+    }
+
+    pub fn remove(&mut self, token: Token)->Option<OperatorEntry<'a>> {
+        let token = Self::key(token);
+        let index = self.entries.iter().position(|e|e.token == token)?;
+
+        return Some(self.entries.remove(index));
+    }
+
+    pub fn get(&self, token: Token)->Option<&OperatorEntry<'a>> {
+        let token = Self::key(token);
+        self.entries.iter().find(|e|e.token == token)
+    }
+
+    fn key<'t>(token: Token<'t>)->Token<'t> {
+        match token {
+            Token::Whitespace(_)=>Token::Whitespace(0),
+            t=>t,
+        }
+    }
+}
+impl Default for OperatorTable<'_> {
+    /// The operators of the language itself.
+    fn default()->Self {
+        use Operator::*;
+
+        let mut table = OperatorTable::new();
+
+        table.insert(Token::Keyword(Keyword::And), LogicAnd, (0, 1));
+        table.insert(Token::Keyword(Keyword::Or), LogicOr, (0, 1));
+
+        table.insert(Token::Equal, Equal, (2, 3));
+        table.insert(Token::NotEqual, NotEqual, (2, 3));
+        table.insert(Token::Less, Less, (2, 3));
+        table.insert(Token::LessEqual, LessEqual, (2, 3));
+        table.insert(Token::Greater, Greater, (2, 3));
+        table.insert(Token::GreaterEqual, GreaterEqual, (2, 3));
+
+        // function application is left-associative
+        table.insert(Token::Whitespace(0), Apply, (5, 4));
+
+        table.insert(Token::Add, Add, (6, 7));
+        table.insert(Token::Sub, Sub, (6, 7));
+
+        table.insert(Token::Mul, Mul, (8, 9));
+        table.insert(Token::Div, Div, (8, 9));
+        table.insert(Token::Rem, Rem, (8, 9));
+
+        table.insert(Token::And, And, (10, 11));
+        table.insert(Token::Or, Or, (10, 11));
+        table.insert(Token::Xor, Xor, (10, 11));
+
+        return table;
+    }
+}
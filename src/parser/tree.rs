@@ -1,9 +1,12 @@
 // parser
 
 
-use std::hash::{
-    Hash,
-    Hasher,
+use std::{
+    hash::{
+        Hash,
+        Hasher,
+    },
+    ops::Range,
 };
 use crate::{
     Index,
@@ -16,42 +19,133 @@ use crate::{
 pub enum Stmt {
     FunctionDef {
         is_proc: bool,
+        /// The operator's left binding power if it was declared with `infix`.
+        infix: Option<u8>,
         name: Name,
+        /// The span of the name, so later passes can point at the definition.
+        span: Range<usize>,
         pattern: Pattern,
+        /// The default values of the last parameters, in order.
+        defaults: Vec<Expr>,
+        /// The type after `->`, if any.
+        return_type: Option<ReturnType>,
+        guard: Option<Expr>,
+        /// The text of the `///` comments before the definition.
+        doc: Option<String>,
         block: Block,
     },
     VarDef {
         mutable: bool,
-        name: Name,
+        pattern: Pattern,
+        /// The type written after the pattern, like `let xs: [Number]`.
+        ty: Option<Name>,
         data: Option<Expr>,
     },
+    /// `const NAME = data` at the top level of the file. `data` must be known at compile time.
+    Const {
+        name: Name,
+        span: Range<usize>,
+        data: Expr,
+    },
     VarSet {
         name: Name,
+        /// The span of the name, so later passes can point at the assignment.
+        span: Range<usize>,
+        data: Expr,
+    },
+    /// `set a, b = expr` assigns each target from the corresponding element of the tuple.
+    VarSetMulti {
+        targets: Vec<Name>,
+        /// The span of each target's name.
+        spans: Vec<Range<usize>>,
         data: Expr,
     },
     IfElse {
         condition: Expr,
         block: Block,
         default: Option<Block>,
+        /// The span of the `if`, so later passes can point at the statement.
+        span: Range<usize>,
     },
     Conditional {
         conditions: Vec<Expr>,
         actions: Vec<ConditionalAction>,
+        /// The `else` arm, taken if none of the conditions match.
+        default: Option<ConditionalAction>,
+        /// The span of the `cond`.
+        span: Range<usize>,
+    },
+    Scope {
+        label: Option<Name>,
+        block: Block,
+    },
+    /// Runs the block forever. It can only be left with `break` or `return`.
+    Loop {
+        label: Option<Name>,
+        block: Block,
+        /// The span of the `loop`, so later passes can point at it.
+        span: Range<usize>,
     },
-    Scope(Block),
     Disown(Expr),
+    /// `assert_type expr : Type` is an error if `expr` has a different type. `expr` is never
+    /// evaluated.
+    AssertType {
+        expr: Expr,
+        ty: Name,
+    },
     Return(Option<Expr>),
-    Expr(Expr),
+    /// The value is given to the `scope` expression being left.
+    Break(Option<Name>, Option<Expr>),
+    /// Jumps back to the start of the labeled `scope`, or the innermost one without a label.
+    Continue(Option<Name>),
+    /// A statement after a `#[cfg(feature)]` marker. It is only converted if the feature is
+    /// enabled.
+    Cfg {
+        feature: Name,
+        stmt: Box<Stmt>,
+    },
+    /// The flag is set if the expression ended with a `;`, which keeps the last expression of a
+    /// `func` from being returned.
+    Expr(Expr, bool),
 }
 impl Stmt {
     pub fn print(&self, interner: &StringInterner, indent: usize) {
         for _ in 0..indent {print!(" ")}
         match self {
-            Stmt::Expr(expr)=>{
+            Stmt::Expr(expr, semicolon)=>{
                 expr.print(interner);
+                if *semicolon {
+                    print!(";");
+                }
                 println!();
             },
-            Stmt::FunctionDef{is_proc, name, pattern, block}=>{
+            Stmt::FunctionDef{
+                is_proc,
+                infix,
+                name,
+                pattern,
+                defaults,
+                return_type,
+                guard,
+                doc,
+                block,
+                ..
+            }=>{
+                if let Some(doc) = doc {
+                    for (i, line) in doc.lines().enumerate() {
+                        // the first line is already indented
+                        if i > 0 {
+                            for _ in 0..indent {print!(" ")}
+                        }
+                        println!("/// {line}");
+                    }
+                    for _ in 0..indent {print!(" ")}
+                }
+
+                if let Some(prec) = infix {
+                    print!("infix {prec} ");
+                }
+
                 if *is_proc {
                     print!("proc ");
                 } else {
@@ -60,17 +154,45 @@ impl Stmt {
 
                 print!("{} ", interner.get_string(*name));
 
-                pattern.print(interner);
+                match pattern {
+                    Pattern::Group(items) if defaults.len() > 0=>{
+                        let required = items.len() - defaults.len();
+                        print!("(");
+                        for (i, item) in items.iter().enumerate() {
+                            if i > 0 {print!(", ")}
+                            item.print(interner);
+                            if i >= required {
+                                print!(" = ");
+                                defaults[i - required].print(interner);
+                            }
+                        }
+                        print!(")");
+                    },
+                    _=>pattern.print(interner),
+                }
+
+                if let Some(ty) = return_type {
+                    print!(" -> ");
+                    ty.print(interner);
+                }
+
+                if let Some(guard) = guard {
+                    print!(" if ");
+                    guard.print(interner);
+                }
 
                 println!();
 
                 block.print(interner, indent + 4);
             },
-            Stmt::VarDef{mutable, name, data}=>{
+            Stmt::VarDef{mutable, pattern, ty, data}=>{
                 print!("let ");
                 if *mutable {print!("mut ")}
 
-                print!("{}", interner.get_string(*name));
+                pattern.print(interner);
+                if let Some(ty) = ty {
+                    print!(": {}", interner.get_string(*ty));
+                }
 
                 if let Some(data) = data {
                     print!(" = ");
@@ -79,22 +201,52 @@ impl Stmt {
 
                 println!();
             },
-            Stmt::VarSet{name, data}=>{
+            Stmt::Const{name, data, ..}=>{
+                print!("const {} = ", interner.get_string(*name));
+
+                data.print(interner);
+
+                println!();
+            },
+            Stmt::VarSet{name, data, ..}=>{
                 print!("set {} = ", interner.get_string(*name));
 
                 data.print(interner);
 
                 println!();
             },
-            Stmt::Scope(block)=>{
-                println!("scope");
+            Stmt::VarSetMulti{targets, data, ..}=>{
+                print!("set {}", interner.get_string(targets[0]));
+                for target in &targets[1..] {
+                    print!(", {}", interner.get_string(*target));
+                }
+                print!(" = ");
+
+                data.print(interner);
+
+                println!();
+            },
+            Stmt::Scope{label, block}|Stmt::Loop{label, block, ..}=>{
+                match self {
+                    Stmt::Scope{..}=>print!("scope"),
+                    _=>print!("loop"),
+                }
+                if let Some(label) = label {
+                    print!(" '{}", interner.get_string(*label));
+                }
+                println!();
                 block.print(interner, indent + 4);
             },
             Stmt::Disown(expr)=>{
                 expr.print(interner);
                 println!();
             },
-            Stmt::IfElse{condition, block, default}=>{
+            Stmt::AssertType{expr, ty}=>{
+                print!("assert_type ");
+                expr.print(interner);
+                println!(" : {}", interner.get_string(*ty));
+            },
+            Stmt::IfElse{condition, block, default, ..}=>{
                 print!("if ");
                 condition.print(interner);
                 println!();
@@ -107,20 +259,9 @@ impl Stmt {
                     else_block.print(interner, indent + 4);
                 }
             },
-            Stmt::Conditional{conditions, actions}=>{
+            Stmt::Conditional{conditions, actions, default, ..}=>{
                 println!("cond");
-                for (condition, block) in conditions.iter().zip(actions.iter()) {
-                    for _ in 0..(indent + 4) {print!(" ")}
-                    if condition.is_group() {
-                        condition.print(interner);
-                    } else {
-                        print!("(");
-                        condition.print(interner);
-                        print!(")");
-                    }
-                    print!(" => ");
-                    block.print(interner, indent + 8);
-                }
+                print_cond_arms(conditions, actions, default, interner, indent + 4);
             },
             Stmt::Return(opt_expr)=>{
                 print!("return ");
@@ -129,14 +270,65 @@ impl Stmt {
                 }
                 println!();
             },
+            Stmt::Break(label, value)=>{
+                print!("break");
+                if let Some(label) = label {
+                    print!(" '{}", interner.get_string(*label));
+                }
+                if let Some(value) = value {
+                    print!(" ");
+                    value.print(interner);
+                }
+                println!();
+            },
+            Stmt::Continue(label)=>{
+                print!("continue");
+                if let Some(label) = label {
+                    print!(" '{}", interner.get_string(*label));
+                }
+                println!();
+            },
+            Stmt::Cfg{feature, stmt}=>{
+                println!("#[cfg({})]", interner.get_string(*feature));
+                stmt.print(interner, indent);
+            },
         }
     }
 }
 
+fn print_cond_arms(
+    conditions: &[Expr],
+    actions: &[ConditionalAction],
+    default: &Option<ConditionalAction>,
+    interner: &StringInterner,
+    indent: usize,
+) {
+    for (condition, block) in conditions.iter().zip(actions.iter()) {
+        for _ in 0..indent {print!(" ")}
+        if condition.is_group() {
+            condition.print(interner);
+        } else {
+            print!("(");
+            condition.print(interner);
+            print!(")");
+        }
+        print!(" => ");
+        block.print(interner, indent + 4);
+    }
+
+    if let Some(block) = default {
+        for _ in 0..indent {print!(" ")}
+        print!("else => ");
+        block.print(interner, indent + 4);
+    }
+}
+
 #[derive(Debug)]
 pub enum ConditionalAction {
     Expr(Expr),
     Scope(Block),
+    /// A nested `cond`. This is always a `Stmt::Conditional`.
+    Cond(Box<Stmt>),
 }
 impl ConditionalAction {
     pub fn print(&self, interner: &StringInterner, indent: usize) {
@@ -149,6 +341,38 @@ impl ConditionalAction {
                 println!("scope");
                 block.print(interner, indent);
             },
+            Self::Cond(cond)=>match &**cond {
+                Stmt::Conditional{conditions, actions, default, ..}=>{
+                    println!("cond");
+                    print_cond_arms(conditions, actions, default, interner, indent);
+                },
+                stmt=>stmt.print(interner, 0),
+            },
+        }
+    }
+}
+
+/// The type after `->` in a function definition.
+#[derive(Debug)]
+pub enum ReturnType {
+    Type(Name),
+    /// `(name: Type, ...)` returns a tuple and names each of its elements.
+    Named(Vec<(Name, Name)>),
+}
+impl ReturnType {
+    pub fn print(&self, interner: &StringInterner) {
+        match self {
+            Self::Type(ty)=>print!("{}", interner.get_string(*ty)),
+            Self::Named(outputs)=>{
+                print!("(");
+                for (i, (name, ty)) in outputs.iter().enumerate() {
+                    if i > 0 {
+                        print!(", ");
+                    }
+                    print!("{}: {}", interner.get_string(*name), interner.get_string(*ty));
+                }
+                print!(")");
+            },
         }
     }
 }
@@ -166,13 +390,49 @@ pub enum Expr {
         left: Box<Self>,
         name: Name,
     },
+    /// <expr> as <word>
+    Cast {
+        expr: Box<Self>,
+        ty: Name,
+    },
     /// '[' <expr> (',' <expr>)+ ','? ']'
     Group(Vec<Self>),
-    Var(Name),
-    Number(i64),
+    /// `[a, b, c]`. Every element has the same type.
+    List(Vec<Self>),
+    /// Parentheses around a single expression. They are only kept if the parser was told to
+    /// preserve them, so printing the tree can reproduce them.
+    Paren(Box<Self>),
+    /// The span is kept so later passes can point at the name.
+    Var(Name, Range<usize>),
+    /// The radix is the one the number was written in, so it is printed the same way.
+    Number(i64, Radix),
     String(Index),
+    Char(char),
     Borrow(Box<Self>),
+    BorrowMut(Box<Self>),
     Deref(Box<Self>),
+    Negate(Box<Self>),
+    /// `heap <expr>` always puts the data on the heap.
+    Heap(Box<Self>),
+    /// `stack <expr>` requires the data to stay on the stack.
+    Stack(Box<Self>),
+    /// `typeof <expr>` is the type of the expression. The expression is never evaluated.
+    TypeOf(Box<Self>),
+    /// An anonymous `func` of one argument. Operator sections like `(+ 1)` desugar to this. The
+    /// name is only used for diagnostics.
+    Lambda {
+        name: Name,
+        param: Name,
+        /// The span of the operator, which the parameter stands in for.
+        span: Range<usize>,
+        body: Box<Self>,
+    },
+    /// A labeled `scope` as the value of a `let`. `break 'label value` leaves it with the value,
+    /// and so does the last expression of the block if it runs to the end.
+    Scope {
+        label: Name,
+        block: Block,
+    },
     None,
 }
 impl Expr {
@@ -181,10 +441,14 @@ impl Expr {
         match self {
             Self::None|
                 Self::Group(_)|
+                Self::List(_)|
+                Self::Paren(_)|
                 Self::String(_)|
-                Self::Number(_)|
+                Self::Char(_)|
+                Self::Number(..)|
                 Self::Field{..}|
-                Self::Var(_)=>true,
+                Self::Lambda{..}|
+                Self::Var(..)=>true,
             _=>false,
         }
     }
@@ -218,18 +482,65 @@ impl Expr {
                 }
                 print!(".{}", interner.get_string(*name));
             },
-            Expr::Var(name)=>print!("{}", interner.get_string(*name)),
-            Expr::Number(n)=>print!("{n}"),
+            Expr::Cast{expr, ty}=>{
+                if expr.is_group() {
+                    expr.print(interner);
+                } else {
+                    print!("(");
+                    expr.print(interner);
+                    print!(")");
+                }
+                print!(" as {}", interner.get_string(*ty));
+            },
+            Expr::Var(name, _)=>print!("{}", interner.get_string(*name)),
+            Expr::Number(n, radix)=>print!("{}", radix.format(*n)),
             Expr::String(s)=>print!("\"{}\"", interner.get_string(*s)),
+            Expr::Char(c)=>print!("{c:?}"),
             Expr::None=>print!("None"),
             Expr::Borrow(inner)=>{
                 print!("&");
                 inner.print(interner);
             },
+            Expr::BorrowMut(inner)=>{
+                print!("&mut ");
+                inner.print(interner);
+            },
             Expr::Deref(inner)=>{
                 print!("*");
                 inner.print(interner);
             },
+            Expr::Negate(inner)=>{
+                print!("-");
+                inner.print(interner);
+            },
+            Expr::Heap(inner)|Expr::Stack(inner)|Expr::TypeOf(inner)=>{
+                match self {
+                    Expr::Heap(_)=>print!("heap "),
+                    Expr::Stack(_)=>print!("stack "),
+                    _=>print!("typeof "),
+                }
+                if inner.is_group() {
+                    inner.print(interner);
+                } else {
+                    print!("(");
+                    inner.print(interner);
+                    print!(")");
+                }
+            },
+            Expr::Lambda{param, body, ..}=>{
+                print!("(func {} => ", interner.get_string(*param));
+                body.print(interner);
+                print!(")");
+            },
+            Expr::Scope{label, block}=>{
+                println!("scope '{}", interner.get_string(*label));
+                block.print(interner, 4);
+            },
+            Expr::Paren(inner)=>{
+                print!("(");
+                inner.print(interner);
+                print!(")");
+            },
             Expr::Group(list)=>{
                 if list.len() == 0 {
                     print!("()");
@@ -240,21 +551,79 @@ impl Expr {
                         print!(", ");
                         i.print(interner);
                     }
+                    if list.len() == 1 {
+                        print!(",");
+                    }
                     print!(")");
                 }
             },
+            Expr::List(items)=>{
+                print!("[");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        print!(", ");
+                    }
+                    item.print(interner);
+                }
+                print!("]");
+            },
             // _=>todo!(),
         }
     }
 }
 
-#[derive(Debug)]
+/// The base a number literal was written in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Radix {
+    Binary,
+    Octal,
+    #[default]
+    Decimal,
+    Hex,
+}
+impl Radix {
+    /// Splits the prefix off of a number literal, returning the radix it is written in and its
+    /// digits.
+    pub fn split(num_str: &str)->(Self, &str) {
+        if let Some(digits) = num_str.strip_prefix("0x") {
+            (Radix::Hex, digits)
+        } else if let Some(digits) = num_str.strip_prefix("0b") {
+            (Radix::Binary, digits)
+        } else if let Some(digits) = num_str.strip_prefix("0o") {
+            (Radix::Octal, digits)
+        } else {
+            (Radix::Decimal, num_str)
+        }
+    }
+
+    pub fn base(&self)->u32 {
+        match self {
+            Radix::Binary=>2,
+            Radix::Octal=>8,
+            Radix::Decimal=>10,
+            Radix::Hex=>16,
+        }
+    }
+
+    /// Writes the number in this radix, with its prefix.
+    pub fn format(&self, n: i64)->String {
+        match self {
+            Radix::Binary=>format!("0b{n:b}"),
+            Radix::Octal=>format!("0o{n:o}"),
+            Radix::Decimal=>format!("{n}"),
+            Radix::Hex=>format!("0x{n:X}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Operator {
     // Arithmetic
     Add,
     Sub,
     Mul,
     Div,
+    Rem,
 
     // Bitwise/logic
     And,
@@ -277,29 +646,45 @@ pub enum Operator {
     Apply,
 }
 impl Operator {
-    pub fn print(&self) {
+    /// The operator's symbol without any surrounding whitespace. Application has no symbol, so
+    /// it is an empty string.
+    pub fn symbol(&self)->&'static str {
+        use Operator::*;
+        match self {
+            Add=>"+",
+            Sub=>"-",
+            Mul=>"*",
+            Div=>"/",
+            Rem=>"%",
+
+            And=>"&",
+            Or=>"|",
+            Xor=>"^",
+
+            Equal=>"==",
+            NotEqual=>"!=",
+            Less=>"<",
+            LessEqual=>"<=",
+            Greater=>">",
+            GreaterEqual=>">=",
+
+            LogicAnd=>"and",
+            LogicOr=>"or",
+
+            Apply=>"",
+        }
+    }
+
+    /// Checks if the operator compares its operands and results in a boolean.
+    pub fn is_comparison(&self)->bool {
         use Operator::*;
+        matches!(self, Equal|NotEqual|Less|LessEqual|Greater|GreaterEqual)
+    }
+
+    pub fn print(&self) {
         match self {
-            Add=>print!(" + "),
-            Sub=>print!(" - "),
-            Mul=>print!(" * "),
-            Div=>print!(" / "),
-
-            And=>print!(" & "),
-            Or=>print!(" | "),
-            Xor=>print!(" ^ "),
-
-            Equal=>print!(" == "),
-            NotEqual=>print!(" != "),
-            Less=>print!(" < "),
-            LessEqual=>print!(" <= "),
-            Greater=>print!(" > "),
-            GreaterEqual=>print!(" >= "),
-
-            LogicAnd=>print!(" and "),
-            LogicOr=>print!(" or "),
-
-            Apply=>print!(" "),
+            Operator::Apply=>print!(" "),
+            op=>print!(" {} ", op.symbol()),
         }
     }
 }
@@ -310,11 +695,22 @@ impl Operator {
 #[derive(Debug, Eq)]
 pub enum Pattern {
     Group(Vec<Self>),
-    Name(Name),
+    /// The span is kept so later passes can point at the name.
+    Name(Name, Range<usize>),
     Number(i64),
+    /// Only matches `None`. Unit is matched by an empty group, and a name matches either.
     None,
 }
 impl Pattern {
+    /// The number of arguments this pattern accepts. Anything other than a group is a single
+    /// argument.
+    pub fn arity(&self)->usize {
+        match self {
+            Self::Group(items)=>items.len(),
+            _=>1,
+        }
+    }
+
     pub fn print(&self, interner: &StringInterner) {
         match self {
             Self::Group(items)=>{
@@ -331,7 +727,7 @@ impl Pattern {
                     print!(")");
                 }
             },
-            Self::Name(n)=>print!("{}", interner.get_string(*n)),
+            Self::Name(n, _)=>print!("{}", interner.get_string(*n)),
             Self::Number(n)=>print!("{n}"),
             Self::None=>print!("None"),
         }
@@ -347,7 +743,7 @@ impl Hash for Pattern {
                 }
                 h.write_u8(1);
             },
-            Self::Name(_)=>h.write_u8(2),
+            Self::Name(..)=>h.write_u8(2),
             Self::Number(n)=>{
                 h.write_u8(3);
                 h.write_i64(*n);
@@ -361,7 +757,7 @@ impl PartialEq for Pattern {
         use Pattern::*;
         match (self, o) {
             (Group(l), Group(r))=>l == r,
-            (Name(_), Name(_))=>true,
+            (Name(..), Name(..))=>true,
             (Number(l), Number(r))=>l == r,
             (None, None)=>true,
             _=>false,
@@ -379,3 +775,26 @@ impl Block {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operator_symbols_have_no_padding() {
+        use Operator::*;
+        let symbols = [
+            (Add, "+"), (Sub, "-"), (Mul, "*"), (Div, "/"), (Rem, "%"),
+            (And, "&"), (Or, "|"), (Xor, "^"),
+            (Equal, "=="), (NotEqual, "!="),
+            (Less, "<"), (LessEqual, "<="), (Greater, ">"), (GreaterEqual, ">="),
+            (LogicAnd, "and"), (LogicOr, "or"),
+            (Apply, ""),
+        ];
+
+        for (op, symbol) in symbols {
+            assert_eq!(op.symbol(), symbol);
+        }
+    }
+}
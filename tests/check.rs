@@ -0,0 +1,41 @@
+// runs the compiler binary in `--check` mode
+
+
+use std::{
+    fs::write,
+    process::{Command, Output},
+};
+
+
+/// Writes the source to a file named `name` in the temp directory and checks it.
+fn check(name: &str, source: &str)->Output {
+    let path = std::env::temp_dir().join(name);
+    write(&path, source).unwrap();
+
+    return Command::new(env!("CARGO_BIN_EXE_gc_lang"))
+        .arg("--check")
+        .arg(path)
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn clean_files_pass() {
+    let output = check("gc_lang_check_clean.gc", "func double x\n    x * 2\nlet y = double 4\n");
+
+    assert!(output.status.success());
+    // nothing is dumped, unlike without `--check`
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn errors_fail() {
+    let output = check("gc_lang_check_error.gc", "let y = undefined_name\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("undefined_name"), "{stderr}");
+
+    let output = check("gc_lang_check_parse_error.gc", "let = 1\n");
+    assert_eq!(output.status.code(), Some(1));
+}